@@ -260,7 +260,7 @@ mod models_controller {
             mut signal_events: EventReader<BdsSignal>,
         ) {
             let time = Duration::from_secs_f32(TRASITION_TIME);
-            for BdsSignal { signal: sig } in signal_events.read() {
+            for BdsSignal { signal: sig, .. } in signal_events.read() {
                 if let Ok((mut player, mut transition)) = animation_player.single_mut() {
                     if let Some(current) = transition.get_main_animation() {
                         if *sig == "Rabit_greeting" {
@@ -436,7 +436,7 @@ mod models_controller {
             mut signal_events: EventReader<BdsSignal>,
         ) {
             let time = Duration::from_secs_f32(TRASITION_TIME);
-            for BdsSignal { signal: sig } in signal_events.read() {
+            for BdsSignal { signal: sig, .. } in signal_events.read() {
                 if let Ok((mut player, mut transition)) = animation_player.single_mut() {
                     if let Some(current) = transition.get_main_animation() {
                         if *sig == "Kid_bow" {
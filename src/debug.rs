@@ -26,13 +26,19 @@ fn loaded_orders(ls_query: Query<&LoadedScript, Added<LoadedScript>>) {
     }
 }
 
-fn too_many_selected(ta_query: Query<&TextArea, (With<Selected>, Without<Pending>)>) {
-    let selected_num = ta_query.iter().len();
-    if selected_num > 1 {
-        error!(
-            "there are {:?} non pending selected text areas.",
-            selected_num
-        );
+#[allow(clippy::type_complexity)]
+fn too_many_selected(
+    ta_query: Query<&ChildOf, (With<TextArea>, With<Selected>, Without<Pending>)>,
+    db_query: Query<(Entity, &DialogBox)>,
+) {
+    for (db_entity, db) in &db_query {
+        let selected_num = ta_query.iter().filter(|p| p.parent() == db_entity).count();
+        if selected_num > 1 {
+            error!(
+                "there are {:?} non pending selected text areas in \"{}\".",
+                selected_num, db.name
+            );
+        }
     }
 }
 
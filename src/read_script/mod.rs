@@ -2,33 +2,121 @@ mod parse_bds;
 mod regex;
 
 use crate::prelude::Order;
+use crate::writing::{
+    line_and_column, BdsEvent, BdsSource, DialogCommandRegistry, DialogScriptError,
+    MissingSectionPolicy, ScriptErrorKind, SinkDownType, SinkDownWindow,
+};
 use bevy::{
-    asset::{io::Reader, AssetLoader, LoadContext},
+    asset::{
+        io::Reader, AssetLoader, AssetPath, LoadContext, LoadState, ParseAssetPathError,
+        ReadAssetBytesError,
+    },
     prelude::*,
     reflect::{
         serde::{ReflectDeserializer, ReflectSerializer},
         TypePath,
     },
 };
+pub(crate) use parse_bds::read_script;
 use parse_bds::*;
-use serde::{de::DeserializeSeed, Deserialize};
+use regex::{apply_rules, read_template, TemplateError, TemplateRule};
+use serde::{de::DeserializeSeed, Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    ops::Range,
+    sync::Mutex,
+};
 use thiserror::Error;
 
 #[derive(Component, Debug)]
 pub(crate) struct LoadedScript {
     pub bds_handle_opt: Option<Handle<BMWScript>>,
-    pub bdt_handle_list: Vec<Handle<BMWTemplate>>,
+    pub templates: Vec<Handle<BMWTemplate>>,
+    pub target_section: String,
+    pub order_list: Option<Vec<Order>>,
+    pub section_len: usize,
+    pub call_stack: Vec<ReturnPoint>,
+}
+
+// CallBdsで呼び出す前の読みかけの状態です。ReturnBdsでここに戻ります。
+#[derive(Debug, Clone)]
+pub(crate) struct ReturnPoint {
+    pub bds_handle_opt: Option<Handle<BMWScript>>,
     pub target_section: String,
     pub order_list: Option<Vec<Order>>,
+    pub section_len: usize,
 }
 
-#[derive(Asset, Debug, Deserialize, TypePath)]
+// includeを展開し、テンプレートを当てて見出しごとに解析したものです。
+// テンプレートはダイアログボックスごとに違うので、テンプレートの組ごとにラベルをつけた別の資産にします。
+// ラベルのないものは読み込みの起点で、見出しを持ちません。
+#[derive(Asset, Debug, TypePath)]
 pub(crate) struct BMWScript {
-    pub script: String,
+    sections: HashMap<String, CheckedSection>,
 }
 
-#[derive(Default)]
-pub(crate) struct BMWScriptLoader;
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+pub(crate) struct BMWScriptSettings {
+    pub templates: Vec<String>,
+}
+
+// ordersはスクリプトの順です。offsetsはordersと同じ添字で、
+// ThroghEventとCommandのtext内の位置を持ちます。
+#[derive(Debug, Default)]
+struct ParsedSection {
+    orders: Vec<Order>,
+    offsets: Vec<Option<usize>>,
+    syntax_errors: Vec<(usize, String)>,
+}
+
+// イベントを確かめ終えた見出しです。ordersは読む順の逆に並べてあり、
+// problemsは読み込むたびにdialog_boxを埋めて送ります。
+#[derive(Debug, Default)]
+struct CheckedSection {
+    orders: Vec<Order>,
+    problems: Vec<DialogScriptError>,
+}
+
+// fileをtemplatesを当てて読みます。同じファイルとテンプレートの組なら同じ資産です。
+pub(crate) fn load_script(
+    asset_server: &AssetServer,
+    file: String,
+    templates: &[Handle<BMWTemplate>],
+) -> Handle<BMWScript> {
+    let templates = templates
+        .iter()
+        .filter_map(Handle::path)
+        .map(ToString::to_string)
+        .collect::<Vec<_>>();
+    let path = AssetPath::from(file).with_label(template_label(&templates));
+    asset_server.load_with_settings(path, move |s: &mut BMWScriptSettings| {
+        s.templates.clone_from(&templates);
+    })
+}
+
+fn template_label(templates: &[String]) -> String {
+    format!("templates:{}", templates.join(","))
+}
+
+// ホットリロードでは設定なしで読み直されるので、ファイルごとに使われたテンプレートの組を覚えておき、
+// 読むたびにすべての組を解析し直します。
+pub(crate) struct BMWScriptLoader {
+    type_registry: AppTypeRegistry,
+    command_registry: DialogCommandRegistry,
+    template_sets: Mutex<HashMap<AssetPath<'static>, HashSet<Vec<String>>>>,
+}
+
+impl FromWorld for BMWScriptLoader {
+    fn from_world(world: &mut World) -> Self {
+        BMWScriptLoader {
+            type_registry: world.resource::<AppTypeRegistry>().clone(),
+            command_registry: world
+                .get_resource_or_init::<DialogCommandRegistry>()
+                .clone(),
+            template_sets: Mutex::default(),
+        }
+    }
+}
 
 #[non_exhaustive]
 #[derive(Debug, Error)]
@@ -39,23 +127,73 @@ pub(crate) enum BMWScriptLoaderError {
     /// A [String](std::string) Error
     #[error("Could not read utf8: {0}")]
     ReadingStringError(#[from] std::string::FromUtf8Error),
+    /// An include directive has a malformed path
+    #[error("Could not parse include path: {0}")]
+    IncludePath(#[from] ParseAssetPathError),
+    /// An included script could not be read
+    #[error("Could not read included script: {0}")]
+    Include(#[from] ReadAssetBytesError),
+    /// An included section does not exist
+    #[error("Could not find included section: {0}")]
+    MissingSection(String),
+    /// A script includes a section that is already being included
+    #[error("Script includes itself: {0}")]
+    RecursiveInclude(String),
 }
 
 impl AssetLoader for BMWScriptLoader {
     type Asset = BMWScript;
-    type Settings = ();
+    type Settings = BMWScriptSettings;
     type Error = BMWScriptLoaderError;
     async fn load(
         &self,
         reader: &mut dyn Reader,
-        _settings: &(),
-        _load_context: &mut LoadContext<'_>,
+        settings: &BMWScriptSettings,
+        load_context: &mut LoadContext<'_>,
     ) -> Result<Self::Asset, Self::Error> {
         let mut bytes = Vec::new();
         reader.read_to_end(&mut bytes).await?;
         let raw_text = String::from_utf8(bytes)?;
-        let bds = BMWScript { script: raw_text };
-        Ok(bds)
+        let script = expand_includes(raw_text, load_context).await?;
+        let path = load_context.asset_path().clone_owned();
+        let template_sets = match self.template_sets.lock() {
+            Ok(mut known) => {
+                let sets = known.entry(path.clone()).or_default();
+                sets.insert(settings.templates.clone());
+                sets.clone()
+            }
+            Err(_) => HashSet::from([settings.templates.clone()]),
+        };
+        for templates in template_sets {
+            let mut rules = Vec::new();
+            let mut is_broken = false;
+            // 読めないテンプレートがある組は作らず、その組を待つダイアログボックスに知らせます。
+            for template in &templates {
+                let loaded = load_context
+                    .loader()
+                    .immediate()
+                    .load::<BMWTemplate>(template.as_str())
+                    .await;
+                match loaded {
+                    Ok(t) => rules.extend(t.get().rules.iter().cloned()),
+                    Err(_) => is_broken = true,
+                }
+            }
+            if is_broken {
+                continue;
+            }
+            let text = apply_rules(&script, &rules);
+            let sections = check_sections(
+                &text,
+                &path.to_string(),
+                &self.type_registry,
+                &self.command_registry,
+            );
+            load_context.add_labeled_asset(template_label(&templates), BMWScript { sections });
+        }
+        Ok(BMWScript {
+            sections: HashMap::new(),
+        })
     }
 
     fn extensions(&self) -> &[&str] {
@@ -63,9 +201,82 @@ impl AssetLoader for BMWScriptLoader {
     }
 }
 
-#[derive(Asset, Debug, Deserialize, TypePath)]
+// include先はバイト列のまま読み、展開はこのファイルの中で行います。
+// read_asset_bytesで読んだファイルも依存関係になるので、include先を書き換えるとホットリロードされます。
+async fn expand_includes(
+    raw_text: String,
+    load_context: &mut LoadContext<'_>,
+) -> Result<String, BMWScriptLoaderError> {
+    let root = load_context.asset_path().clone_owned();
+    let mut sources = HashMap::from([(root.clone(), raw_text)]);
+    let mut unread = vec![root.clone()];
+    while let Some(path) = unread.pop() {
+        for (_, uri) in find_includes(&sources[&path]) {
+            let target = include_target(&path, &uri)?.0;
+            if sources.contains_key(&target) {
+                continue;
+            }
+            let bytes = load_context.read_asset_bytes(&target).await?;
+            sources.insert(target.clone(), String::from_utf8(bytes)?);
+            unread.push(target);
+        }
+    }
+    let mut stack = vec![(root.clone(), String::new())];
+    splice_includes(&sources, &sources[&root], &mut stack)
+}
+
+// stackは展開中のファイルと見出しの組です。同じ組がもう一度出てきたときだけ循環とみなすので、
+// 同じファイルの別の見出しはincludeできます。見出しが空の組はファイル全体です。
+fn splice_includes(
+    sources: &HashMap<AssetPath<'static>, String>,
+    text: &str,
+    stack: &mut Vec<(AssetPath<'static>, String)>,
+) -> Result<String, BMWScriptLoaderError> {
+    let mut expanded = String::new();
+    let mut last = 0;
+    for (range, uri) in find_includes(text) {
+        expanded.push_str(&text[last..range.start]);
+        last = range.end;
+        let Some((current, _)) = stack.last() else {
+            break;
+        };
+        let key = include_target(current, &uri)?;
+        if stack.contains(&key) {
+            return Err(BMWScriptLoaderError::RecursiveInclude(uri));
+        }
+        let source = &sources[&key.0];
+        let included = if key.1.is_empty() {
+            source.clone()
+        } else {
+            extract_section(source, &key.1)
+                .ok_or_else(|| BMWScriptLoaderError::MissingSection(uri.clone()))?
+        };
+        stack.push(key);
+        expanded.push_str(&splice_includes(sources, &included, stack)?);
+        stack.pop();
+    }
+    expanded.push_str(&text[last..]);
+    Ok(expanded)
+}
+
+// ファイル名を省いた`#Section`は同じファイルの見出しです。
+fn include_target(
+    current: &AssetPath<'static>,
+    uri: &str,
+) -> Result<(AssetPath<'static>, String), ParseAssetPathError> {
+    let (file, section) = split_path_and_section(uri);
+    let path = if file.is_empty() {
+        current.clone()
+    } else {
+        current.resolve_embed(&file)?
+    };
+    Ok((path, section))
+}
+
+#[derive(Asset, Debug, TypePath)]
 pub(crate) struct BMWTemplate {
-    pub template: String,
+    // ローダーでコンパイル済みなので、スクリプトを読むたびに作り直しません。
+    pub rules: Vec<TemplateRule>,
 }
 
 #[derive(Default)]
@@ -80,6 +291,9 @@ pub(crate) enum BMWTemplateLoaderError {
     /// A [String](std::string) Error
     #[error("Could not read utf8: {0}")]
     ReadingStringError(#[from] std::string::FromUtf8Error),
+    /// A row of the template can not be used
+    #[error("Invalid template: {0}")]
+    Template(#[from] TemplateError),
 }
 
 impl AssetLoader for BMWTemplateLoader {
@@ -95,7 +309,8 @@ impl AssetLoader for BMWTemplateLoader {
         let mut bytes = Vec::new();
         reader.read_to_end(&mut bytes).await?;
         let raw_text = String::from_utf8(bytes)?;
-        let bdt = BMWTemplate { template: raw_text };
+        let rules = read_template(&raw_text)?;
+        let bdt = BMWTemplate { rules };
         Ok(bdt)
     }
 
@@ -104,41 +319,236 @@ impl AssetLoader for BMWTemplateLoader {
     }
 }
 
+// 壊れたタグや読めないイベントはローダーで見つけてあるので、DialogScriptErrorとして送ります。
+// 見出しを移るときは解析済みの命令列を写すだけです。
 pub(crate) fn script_on_load(
-    mut loaded_script_query: Query<&mut LoadedScript>,
+    mut loaded_script_query: Query<(Entity, &mut LoadedScript, Option<&MissingSectionPolicy>)>,
     script_assets: Res<Assets<BMWScript>>,
-    template_assets: Res<Assets<BMWTemplate>>,
+    asset_server: Res<AssetServer>,
+    mut error_events: EventWriter<DialogScriptError>,
+    mut bds_events: EventWriter<BdsEvent>,
+) {
+    for (db_entity, mut loaded_script, policy) in &mut loaded_script_query {
+        if loaded_script.order_list.is_some() {
+            continue;
+        }
+        let Some(handle) = loaded_script.bds_handle_opt.clone() else {
+            continue;
+        };
+        let path = handle
+            .path()
+            .map(|p| p.without_label().to_string())
+            .unwrap_or_default();
+        let failed = loaded_script.templates.iter().find_map(|template| {
+            match asset_server.load_state(template) {
+                LoadState::Failed(e) => Some((template.path(), e)),
+                _ => None,
+            }
+        });
+        if let Some((template_path, e)) = failed {
+            error_events.write(DialogScriptError {
+                kind: ScriptErrorKind::InvalidTemplate(e.to_string()),
+                dialog_box: Some(db_entity),
+                path,
+                section: loaded_script.target_section.clone(),
+                line: 0,
+                column: 0,
+                snippet: template_path.map(|p| p.to_string()).unwrap_or_default(),
+            });
+            stop_reading(&mut loaded_script, db_entity, policy, &mut bds_events);
+            continue;
+        }
+        let Some(bds) = script_assets.get(&handle) else {
+            continue;
+        };
+        let section = loaded_script.target_section.clone();
+        let Some(checked) = bds.sections.get(&section) else {
+            error_events.write(DialogScriptError {
+                kind: ScriptErrorKind::MissingSection,
+                dialog_box: Some(db_entity),
+                path,
+                section: section.clone(),
+                line: 0,
+                column: 0,
+                snippet: section.clone(),
+            });
+            match policy {
+                // 代わりのsectionもなければ閉じます。
+                Some(MissingSectionPolicy::Fallback { section: fallback })
+                    if *fallback != section =>
+                {
+                    loaded_script.target_section = fallback.clone();
+                }
+                _ => stop_reading(&mut loaded_script, db_entity, policy, &mut bds_events),
+            }
+            continue;
+        };
+        for problem in &checked.problems {
+            error_events.write(DialogScriptError {
+                dialog_box: Some(db_entity),
+                ..problem.clone()
+            });
+        }
+        loaded_script.section_len = checked.orders.len();
+        loaded_script.order_list = Some(checked.orders.clone());
+    }
+}
+
+// 読めるものがないときは、書き終わったときと同じように入力を待ってから箱を閉じます。
+// sink_typeはCloseに書かれたもので、それ以外のときは既定のものを使います。
+fn stop_reading(
+    loaded_script: &mut LoadedScript,
+    db_entity: Entity,
+    policy: Option<&MissingSectionPolicy>,
+    bds_events: &mut EventWriter<BdsEvent>,
 ) {
-    for mut loaded_script in &mut loaded_script_query {
-        if loaded_script.order_list.is_none() {
-            let script_opt = loaded_script
-                .bds_handle_opt
-                .clone()
-                .and_then(|x| script_assets.get(&x));
-            let template_list = loaded_script
-                .bdt_handle_list
-                .iter()
-                .filter_map(|x| template_assets.get(x))
-                .map(|x| x.template.clone())
-                .collect::<Vec<_>>();
-            if let Some(bds) = script_opt {
-                let parsed =
-                    parse_script(&bds.script, &template_list, &loaded_script.target_section);
-                loaded_script.order_list = Some(parsed);
+    let sink_type = match policy {
+        Some(MissingSectionPolicy::Close { sink_type }) => *sink_type,
+        _ => SinkDownType::default(),
+    };
+    bds_events.write(BdsEvent {
+        value: Box::new(SinkDownWindow { sink_type }),
+        dialog_box: Some(db_entity),
+        source: BdsSource::default(),
+    });
+    loaded_script.section_len = 0;
+    loaded_script.order_list = Some(Vec::new());
+}
+
+// イベントの型を引けない命令を取り除き、見つけた問題を位置つきで残します。
+fn check_sections(
+    text: &str,
+    path: &str,
+    type_registry: &AppTypeRegistry,
+    command_registry: &DialogCommandRegistry,
+) -> HashMap<String, CheckedSection> {
+    let mut checked_sections = HashMap::new();
+    for (section, parsed) in parse_sections(text) {
+        let mut problems = Vec::new();
+        let mut report = |kind, offset: Option<usize>, snippet: &str| {
+            let (line, column) = offset.map_or((0, 0), |o| line_and_column(text, o));
+            problems.push(DialogScriptError {
+                kind,
+                dialog_box: None,
+                path: path.to_string(),
+                section: section.clone(),
+                line,
+                column,
+                snippet: snippet.trim().to_string(),
+            });
+        };
+        for (offset, snippet) in &parsed.syntax_errors {
+            report(ScriptErrorKind::Syntax, Some(*offset), snippet);
+        }
+        let mut orders = Vec::with_capacity(parsed.orders.len());
+        for (order, found) in parsed.orders.into_iter().zip(parsed.offsets) {
+            let (snippet, ron_opt) = match &order {
+                Order::ThroghEvent { ron } => (ron.as_str(), Some(ron.clone())),
+                Order::Command { name, args } => {
+                    let snippet = found.map_or(name.as_str(), |o| command_snippet(&text[o..]));
+                    (snippet, command_registry.to_ron(name, args))
+                }
+                _ => {
+                    orders.push(order);
+                    continue;
+                }
+            };
+            let Some(ron) = ron_opt else {
+                report(ScriptErrorKind::UnknownCommand, found, snippet);
+                continue;
+            };
+            match read_ron(type_registry, &ron) {
+                Ok(_) => orders.push(order),
+                Err(e) => {
+                    // ThroghEventならron内の位置まで指します。
+                    let offset = match order {
+                        Order::ThroghEvent { .. } => {
+                            found.map(|o| o + ron_offset(&ron, e.position))
+                        }
+                        _ => found,
+                    };
+                    let kind = ScriptErrorKind::InvalidEvent(e.code.to_string());
+                    report(kind, offset, snippet);
+                }
             }
         }
+        orders.reverse();
+        checked_sections.insert(section, CheckedSection { orders, problems });
     }
+    checked_sections
+}
+
+// 同じronが何度も出てくることがあるので、見つけた位置より後ろを探していきます。
+fn parse_sections(text: &str) -> HashMap<String, ParsedSection> {
+    let ranges = section_ranges(text);
+    read_bds(text)
+        .into_iter()
+        .map(|(name, orders)| {
+            let range = ranges.get(&name).cloned().unwrap_or(0..text.len());
+            let offsets = locate_orders(text, range.clone(), &orders);
+            let syntax_errors = find_syntax_errors(&text[range.clone()])
+                .into_iter()
+                .map(|(offset, snippet)| (range.start + offset, snippet))
+                .collect();
+            let parsed = ParsedSection {
+                orders,
+                offsets,
+                syntax_errors,
+            };
+            (name, parsed)
+        })
+        .collect()
+}
+
+fn locate_orders(text: &str, range: Range<usize>, orders: &[Order]) -> Vec<Option<usize>> {
+    let mut cursor = range.start;
+    orders
+        .iter()
+        .map(|order| {
+            let needle = match order {
+                Order::ThroghEvent { ron } => ron.clone(),
+                Order::Command { name, .. } => format!("[@{name}"),
+                _ => return None,
+            };
+            let found = text[cursor..range.end].find(&needle).map(|i| cursor + i);
+            if let Some(offset) = found {
+                cursor = offset + needle.len();
+            }
+            found
+        })
+        .collect()
+}
+
+fn command_snippet(rest: &str) -> &str {
+    rest.find(']')
+        .map_or(rest.lines().next().unwrap_or_default(), |i| &rest[..=i])
+}
+
+// ronの行と列（バイト数）をron内のバイト位置に直します。
+fn ron_offset(ron: &str, position: ron::error::Position) -> usize {
+    let line_start = ron
+        .split_inclusive('\n')
+        .take(position.line.saturating_sub(1))
+        .map(str::len)
+        .sum::<usize>();
+    let mut offset = (line_start + position.col.saturating_sub(1)).min(ron.len());
+    while !ron.is_char_boundary(offset) {
+        offset -= 1;
+    }
+    offset
 }
 
 pub(crate) fn read_ron<S: AsRef<str>>(
     type_registry: &AppTypeRegistry,
     ron: S,
-) -> Result<Box<dyn PartialReflect>, ron::Error> {
+) -> Result<Box<dyn PartialReflect>, ron::error::SpannedError> {
     let ron_string = ron.as_ref().to_string();
     let reg = type_registry.read();
     let reflect_deserializer = ReflectDeserializer::new(&reg);
     let mut deserializer = ron::de::Deserializer::from_str(&ron_string)?;
-    reflect_deserializer.deserialize(&mut deserializer)
+    reflect_deserializer
+        .deserialize(&mut deserializer)
+        .map_err(|e| deserializer.span_error(e))
 }
 
 pub(crate) fn split_path_and_section<S: AsRef<str>>(uri: S) -> (String, String) {
@@ -158,7 +568,146 @@ pub(crate) fn parse_script<S1: AsRef<str>, S2: AsRef<str>, S3: AsRef<str>>(
     base: S1,
     templates: &[S2],
     section: S3,
-) -> Vec<Order> {
-    let orders = read_script(base, templates);
-    orders[section.as_ref()].clone().into_iter().rev().collect()
+) -> Option<Vec<Order>> {
+    let mut orders = read_script(base, templates).ok()?;
+    let section_orders = orders.remove(section.as_ref())?;
+    Some(section_orders.into_iter().rev().collect())
+}
+
+#[cfg(test)]
+mod include_tests {
+    use super::*;
+
+    fn sources(files: &[(&str, &str)]) -> HashMap<AssetPath<'static>, String> {
+        files
+            .iter()
+            .map(|(path, text)| (AssetPath::from(path.to_string()), text.to_string()))
+            .collect()
+    }
+
+    fn expand(files: &[(&str, &str)]) -> Result<String, BMWScriptLoaderError> {
+        let sources = sources(files);
+        let root = AssetPath::from(files[0].0.to_string());
+        let mut stack = vec![(root.clone(), String::new())];
+        splice_includes(&sources, &sources[&root], &mut stack)
+    }
+
+    #[test]
+    fn test_include_section() {
+        let files = [
+            ("scripts/a.md", "# A\n<!-- include: b.md#B -->"),
+            ("scripts/b.md", "# B\nhello\n# C\nbye\n"),
+        ];
+        assert_eq!(expand(&files).unwrap(), "# A\nhello\n");
+    }
+
+    #[test]
+    fn test_include_other_section_of_same_file() {
+        let files = [("a.md", "# A\n<!-- include: a.md#B -->\n# B\nhello\n")];
+        assert_eq!(expand(&files).unwrap(), "# A\nhello\n\n# B\nhello\n");
+        let files = [("a.md", "# A\n<!-- include: #B -->\n# B\nhello\n")];
+        assert_eq!(expand(&files).unwrap(), "# A\nhello\n\n# B\nhello\n");
+    }
+
+    #[test]
+    fn test_indirect_recursive_include() {
+        let files = [
+            ("a.md", "# A\n<!-- include: b.md#B -->"),
+            ("b.md", "# B\n<!-- include: a.md#A -->"),
+        ];
+        assert!(matches!(
+            expand(&files),
+            Err(BMWScriptLoaderError::RecursiveInclude(uri)) if uri == "b.md#B"
+        ));
+        let files = [("a.md", "# A\n<!-- include: a.md -->")];
+        assert!(matches!(
+            expand(&files),
+            Err(BMWScriptLoaderError::RecursiveInclude(_))
+        ));
+    }
+}
+
+#[cfg(test)]
+mod loader_tests {
+    use super::*;
+    use bevy::asset::io::embedded::EmbeddedAssetRegistry;
+    use std::path::{Path, PathBuf};
+
+    fn loader_app(files: &[(&str, &'static str)]) -> App {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, AssetPlugin::default()))
+            .init_asset::<BMWScript>()
+            .init_asset::<BMWTemplate>()
+            .init_asset_loader::<BMWScriptLoader>()
+            .init_asset_loader::<BMWTemplateLoader>();
+        let registry = app.world().resource::<EmbeddedAssetRegistry>();
+        for (path, text) in files {
+            registry.insert_asset(PathBuf::new(), Path::new(path), text.as_bytes());
+        }
+        app
+    }
+
+    fn wait_for(app: &mut App, handle: &Handle<BMWScript>) {
+        for _ in 0..200 {
+            app.update();
+            if app.world().resource::<Assets<BMWScript>>().contains(handle) {
+                return;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(2));
+        }
+        panic!("{:?} was not loaded", handle.path());
+    }
+
+    fn texts(app: &App, handle: &Handle<BMWScript>, section: &str) -> String {
+        let bds = app.world().resource::<Assets<BMWScript>>().get(handle);
+        bds.and_then(|b| b.sections.get(section))
+            .map(|s| {
+                s.orders
+                    .iter()
+                    .rev()
+                    .filter_map(|o| match o {
+                        Order::Text { text } => Some(text.as_str()),
+                        _ => None,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn test_same_script_with_other_templates_is_other_asset() {
+        let mut app = loader_app(&[
+            ("test/a.md", "# A\nhello\n# B\nworld\n"),
+            ("test/t.csv", "\"hello\",\"bye\"\n"),
+        ]);
+        let asset_server = app.world().resource::<AssetServer>().clone();
+        let template = asset_server.load::<BMWTemplate>("embedded://test/t.csv");
+        let plain = load_script(&asset_server, "embedded://test/a.md".to_string(), &[]);
+        let templated = load_script(
+            &asset_server,
+            "embedded://test/a.md".to_string(),
+            &[template],
+        );
+        assert_ne!(plain.id(), templated.id());
+        wait_for(&mut app, &plain);
+        wait_for(&mut app, &templated);
+        assert_eq!(texts(&app, &plain, "A"), "hello");
+        assert_eq!(texts(&app, &templated, "A"), "bye");
+        // 別の見出しも同じ資産から引けます。
+        assert_eq!(texts(&app, &templated, "B"), "world");
+    }
+
+    #[test]
+    fn test_loader_reports_unknown_commands() {
+        let mut app = loader_app(&[("test/c.md", "# A\nhi[@nope x=1]\n")]);
+        let asset_server = app.world().resource::<AssetServer>().clone();
+        let handle = load_script(&asset_server, "embedded://test/c.md".to_string(), &[]);
+        wait_for(&mut app, &handle);
+        let scripts = app.world().resource::<Assets<BMWScript>>();
+        let section = &scripts.get(&handle).unwrap().sections["A"];
+        assert_eq!(section.problems.len(), 1);
+        assert_eq!(section.problems[0].kind, ScriptErrorKind::UnknownCommand);
+        assert_eq!(section.problems[0].path, "embedded://test/c.md");
+        assert_eq!(texts(&app, &handle, "A"), "hi");
+    }
 }
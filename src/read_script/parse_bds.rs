@@ -6,27 +6,51 @@ use nom::multi::*;
 use nom::sequence::*;
 use nom::*;
 use std::collections::HashMap;
+use std::ops::Range;
 
-use super::regex::replace_by_template;
+use super::regex::{replace_by_template, TemplateError};
 use super::Order;
 
 #[derive(Clone, Debug, PartialEq)]
 enum ParsedOrder {
     OrderWrapper(Order),
+    // 続く文字はread_bdsで一つのOrder::Textにまとめます。
+    Char(char),
     SectionLine(String),
+    // 閉じていないタグやコマンドです。rest_lenはそこからの残りの長さです。
+    Broken { rest_len: usize, snippet: String },
     Empty,
 }
 
 pub(crate) fn read_script<S1: AsRef<str>, S2: AsRef<str>>(
     input: S1,
     templates: &[S2],
-) -> HashMap<String, Vec<Order>> {
+) -> Result<HashMap<String, Vec<Order>>, TemplateError> {
+    let replaced = apply_templates(input, templates)?;
+    // print!("{replaced}");
+    Ok(read_bds(replaced))
+}
+
+fn apply_templates<S1: AsRef<str>, S2: AsRef<str>>(
+    input: S1,
+    templates: &[S2],
+) -> Result<String, TemplateError> {
     let mut replaced = String::from(input.as_ref());
     for t in templates {
-        replaced = replace_by_template(replaced, t);
+        replaced = replace_by_template(replaced, t)?;
     }
-    // print!("{replaced}");
-    read_bds(replaced)
+    Ok(replaced)
+}
+
+// 読み飛ばしたタグやコマンドの位置と中身を返します。
+pub(crate) fn find_syntax_errors(input: &str) -> Vec<(usize, String)> {
+    parse_bds(input)
+        .into_iter()
+        .filter_map(|p| match p {
+            ParsedOrder::Broken { rest_len, snippet } => Some((input.len() - rest_len, snippet)),
+            _ => None,
+        })
+        .collect()
 }
 
 pub(crate) fn parse_uri(uri: &str) -> (String, String) {
@@ -52,13 +76,66 @@ pub(crate) fn read_bds<S: AsRef<str>>(input: S) -> HashMap<String, Vec<Order>> {
                 next_list = vec![]
             }
             ParsedOrder::OrderWrapper(o) => next_list.push(o),
-            ParsedOrder::Empty => (),
+            ParsedOrder::Char(c) => match next_list.last_mut() {
+                Some(Order::Text { text }) => text.push(c),
+                _ => next_list.push(Order::Text {
+                    text: c.to_string(),
+                }),
+            },
+            ParsedOrder::Broken { .. } | ParsedOrder::Empty => (),
         }
     }
     section_map.insert(next_head, next_list);
     section_map
 }
 
+// <!-- include: path#section --> の範囲と、その中のpath#sectionを返します。
+pub(crate) fn find_includes(input: &str) -> Vec<(Range<usize>, String)> {
+    let open = tuple((tag("<!--"), space0, tag("include:"), space0));
+    let mut directive = delimited(open, take_until("-->"), tag("-->"));
+    input
+        .match_indices("<!--")
+        .filter_map(|(start, _)| {
+            let parsed: IResult<&str, &str> = directive(&input[start..]);
+            let (rem, uri) = parsed.ok()?;
+            Some((start..input.len() - rem.len(), uri.trim().to_string()))
+        })
+        .collect()
+}
+
+// 見出しの次から次の見出しの手前までを返します。見出しの判定はsection_headと同じです。
+pub(crate) fn extract_section(input: &str, section: &str) -> Option<String> {
+    section_ranges(input)
+        .remove(section)
+        .map(|r| input[r].to_string())
+}
+
+// 空のsectionは最初の見出しより前の部分です。同じ名前の見出しは最初のものを使います。
+pub(crate) fn section_ranges(input: &str) -> HashMap<String, Range<usize>> {
+    let mut heads = Vec::new();
+    let mut pos = 0;
+    while pos < input.len() {
+        let rest = &input[pos..];
+        let is_line_head = pos == 0 || input[..pos].ends_with('\n');
+        if is_line_head || rest.starts_with("<h1") {
+            if let Ok((rem, ParsedOrder::SectionLine(name))) = section_head(rest) {
+                let end = input.len() - rem.len();
+                heads.push((pos, end, name));
+                pos = end;
+                continue;
+            }
+        }
+        pos += rest.chars().next().map_or(1, char::len_utf8);
+    }
+    let mut ranges = HashMap::new();
+    ranges.insert(String::new(), 0..heads.first().map_or(input.len(), |h| h.0));
+    for (index, (_, start, name)) in heads.iter().enumerate() {
+        let end = heads.get(index + 1).map_or(input.len(), |h| h.0);
+        ranges.entry(name.clone()).or_insert(*start..end);
+    }
+    ranges
+}
+
 fn parse_bds(input: &str) -> Vec<ParsedOrder> {
     let mut bds_parser = many0(alt((
         backslash,
@@ -68,6 +145,9 @@ fn parse_bds(input: &str) -> Vec<ParsedOrder> {
         choice,
         jump_event,
         throw_event,
+        call_command,
+        echo_variable,
+        broken_tag,
         next_line,
         erase_useless_tag,
         simple_char,
@@ -86,91 +166,31 @@ fn backslash(input: &str) -> IResult<&str, ParsedOrder> {
     preceded(
         char('\\'),
         alt((
-            value(
-                ParsedOrder::OrderWrapper(Order::Type { character: '\\' }),
-                char('\\'),
-            ),
-            value(
-                ParsedOrder::OrderWrapper(Order::Type { character: '<' }),
-                char('<'),
-            ),
-            value(
-                ParsedOrder::OrderWrapper(Order::Type { character: '>' }),
-                char('>'),
-            ),
-            value(
-                ParsedOrder::OrderWrapper(Order::Type { character: '`' }),
-                char('`'),
-            ),
-            value(
-                ParsedOrder::OrderWrapper(Order::Type { character: '{' }),
-                char('{'),
-            ),
-            value(
-                ParsedOrder::OrderWrapper(Order::Type { character: '}' }),
-                char('}'),
-            ),
-            value(
-                ParsedOrder::OrderWrapper(Order::Type { character: '[' }),
-                char('['),
-            ),
-            value(
-                ParsedOrder::OrderWrapper(Order::Type { character: ']' }),
-                char(']'),
-            ),
-            value(
-                ParsedOrder::OrderWrapper(Order::Type { character: '_' }),
-                char('_'),
-            ),
-            value(
-                ParsedOrder::OrderWrapper(Order::Type { character: '*' }),
-                char('*'),
-            ),
-            value(
-                ParsedOrder::OrderWrapper(Order::Type { character: '+' }),
-                char('+'),
-            ),
-            value(
-                ParsedOrder::OrderWrapper(Order::Type { character: '(' }),
-                char('('),
-            ),
-            value(
-                ParsedOrder::OrderWrapper(Order::Type { character: ')' }),
-                char(')'),
-            ),
-            value(
-                ParsedOrder::OrderWrapper(Order::Type { character: '#' }),
-                char('#'),
-            ),
-            value(
-                ParsedOrder::OrderWrapper(Order::Type { character: '.' }),
-                char('.'),
-            ),
-            value(
-                ParsedOrder::OrderWrapper(Order::Type { character: '!' }),
-                char('!'),
-            ),
-            value(
-                ParsedOrder::OrderWrapper(Order::Type { character: '|' }),
-                char('|'),
-            ),
-            value(
-                ParsedOrder::OrderWrapper(Order::Type { character: '&' }),
-                char('&'),
-            ),
+            value(ParsedOrder::Char('\\'), char('\\')),
+            value(ParsedOrder::Char('<'), char('<')),
+            value(ParsedOrder::Char('>'), char('>')),
+            value(ParsedOrder::Char('`'), char('`')),
+            value(ParsedOrder::Char('{'), char('{')),
+            value(ParsedOrder::Char('}'), char('}')),
+            value(ParsedOrder::Char('['), char('[')),
+            value(ParsedOrder::Char(']'), char(']')),
+            value(ParsedOrder::Char('_'), char('_')),
+            value(ParsedOrder::Char('*'), char('*')),
+            value(ParsedOrder::Char('+'), char('+')),
+            value(ParsedOrder::Char('('), char('(')),
+            value(ParsedOrder::Char(')'), char(')')),
+            value(ParsedOrder::Char('#'), char('#')),
+            value(ParsedOrder::Char('.'), char('.')),
+            value(ParsedOrder::Char('!'), char('!')),
+            value(ParsedOrder::Char('|'), char('|')),
+            value(ParsedOrder::Char('&'), char('&')),
         )),
     )(input)
 }
 
 fn ampersand(input: &str) -> IResult<&str, ParsedOrder> {
-    let nbsp = value(
-        ParsedOrder::OrderWrapper(Order::Type { character: ' ' }),
-        tag("&nbsp;"),
-    );
-    let emsp = value(
-        ParsedOrder::OrderWrapper(Order::Type { character: '　' }),
-        tag("&emsp;"),
-    );
+    let nbsp = value(ParsedOrder::Char(' '), tag("&nbsp;"));
+    let emsp = value(ParsedOrder::Char('　'), tag("&emsp;"));
     alt((nbsp, emsp))(input)
 }
 
@@ -199,9 +219,7 @@ fn simple_char(input: &str) -> IResult<&str, ParsedOrder> {
         let order = if c == "\n" || c == "\r" || c == "\t" {
             ParsedOrder::Empty
         } else {
-            ParsedOrder::OrderWrapper(Order::Type {
-                character: c.chars().next().unwrap(),
-            })
+            ParsedOrder::Char(c.chars().next().unwrap())
         };
         (rem, order)
     })
@@ -254,92 +272,159 @@ fn throw_event(input: &str) -> IResult<&str, ParsedOrder> {
     })
 }
 
+// [@name key=value key="string"] をOrder::Commandにします。
+// 型はDialogCommandRegistryから実行時に引くので、ここでは名前と値の文字列だけ持ちます。
+fn call_command(input: &str) -> IResult<&str, ParsedOrder> {
+    let arg = separated_pair(command_name, char('='), command_value);
+    let args = many0(preceded(space1, arg));
+    let command = delimited(tag("[@"), pair(command_name, args), pair(space0, char(']')));
+    map(command, |(n, a)| {
+        ParsedOrder::OrderWrapper(Order::Command {
+            name: n.to_string(),
+            args: a
+                .into_iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        })
+    })(input)
+}
+
+// throw_eventやcall_commandになれなかった<scriptや[@です。行末まで読み飛ばします。
+fn broken_tag(input: &str) -> IResult<&str, ParsedOrder> {
+    let broken = recognize(pair(alt((tag("<script"), tag("[@"))), not_line_ending));
+    map(broken, |s: &str| ParsedOrder::Broken {
+        rest_len: input.len(),
+        snippet: s.to_string(),
+    })(input)
+}
+
+// ${name} はScriptVariablesの値に、表示するときに置き換えます。
+fn echo_variable(input: &str) -> IResult<&str, ParsedOrder> {
+    let variable = delimited(tag("${"), command_name, char('}'));
+    map(variable, |n| {
+        ParsedOrder::OrderWrapper(Order::Variable {
+            name: n.to_string(),
+        })
+    })(input)
+}
+
+fn command_name(input: &str) -> IResult<&str, &str> {
+    take_while1(|c: char| c.is_alphanumeric() || c == '_')(input)
+}
+
+fn command_value(input: &str) -> IResult<&str, &str> {
+    let escaped_string = escaped(is_not("\\\""), '\\', anychar);
+    let quoted = recognize(delimited(char('"'), opt(escaped_string), char('"')));
+    alt((quoted, is_not(" \t\r\n]")))(input)
+}
+
 #[allow(clippy::let_and_return)]
 fn jump_event(input: &str) -> IResult<&str, ParsedOrder> {
-    let path_target = separated_pair(is_not(" \t"), space1, is_not(")"));
-    let link = delimited(char('('), path_target, char(')'));
-    let head = r#"{"bevy_novelgame_dialog::writing::events::bds::LoadBds": (path: ""#;
-    let middle = r#"",target_name: "#;
-    let last = r#",),}"#;
-    let to_ron = map(link, |(t, p)| [head, t, middle, p, last].concat());
-    let parsed = map(to_ron, |s| {
-        ParsedOrder::OrderWrapper(Order::ThroghEvent { ron: s })
+    let parsed = map(link, |(p, t)| {
+        ParsedOrder::OrderWrapper(Order::ThroghEvent {
+            ron: link_ron("LoadBds", p, t),
+        })
     })(input);
     parsed
 }
 
-fn jump_string(input: &str) -> IResult<&str, String> {
-    map(jump_event, |o| {
-        if let ParsedOrder::OrderWrapper(Order::ThroghEvent { ron }) = o {
-            ron
-        } else {
-            "".to_string()
-        }
-    })(input)
+fn link(input: &str) -> IResult<&str, (&str, &str)> {
+    let path_target = separated_pair(is_not(" \t"), space1, is_not(")"));
+    delimited(char('('), path_target, char(')'))(input)
 }
 
+// eventはwriting::events::bdsにあるLoadBdsかCallBdsです。
+fn link_ron(event: &str, path: &str, target: &str) -> String {
+    format!(
+        r#"{{"bevy_novelgame_dialog::writing::events::bds::{event}": (path: "{path}",target_name: {target},),}}"#
+    )
+}
+
+// * text(link){show_if=has_key}{enable_if=gold>=10}{call}
+// 条件がひとつもなければconditionsは書き出しません。
+// {call}があればLoadBdsではなくCallBdsで読み込み、[return]で選択肢の次に戻ります。
 #[allow(clippy::let_and_return)]
 fn choice(input: &str) -> IResult<&str, ParsedOrder> {
-    let text_and_link = preceded(tag("* "), many_till(take(1usize), jump_string));
-    let choice_to_string = map(text_and_link, |(s1, s2)| {
-        format!("(\"{}\", \"{}\"),", s1.concat(), s2.replace('\"', "\\\""))
+    let text_and_link = preceded(tag("* "), many_till(take(1usize), link));
+    let with_conditions = pair(text_and_link, many0(choice_condition));
+    let choice_to_string = map(with_conditions, |((s1, (p, t)), mut conditions)| {
+        let is_call = conditions.iter().any(|(k, _)| *k == "call");
+        conditions.retain(|(k, _)| *k != "call");
+        let event = if is_call { "CallBds" } else { "LoadBds" };
+        let ron = link_ron(event, p, t);
+        let target = format!("(\"{}\", \"{}\"),", s1.concat(), ron.replace('\"', "\\\""));
+        let find = |key: &str| {
+            conditions
+                .iter()
+                .rev()
+                .find(|(k, _)| *k == key)
+                .map(|(_, v)| v.replace('\"', "\\\""))
+                .unwrap_or_default()
+        };
+        let condition = format!(
+            "(show_if: \"{}\", enable_if: \"{}\",),",
+            find("show_if"),
+            find("enable_if")
+        );
+        (target, condition, !conditions.is_empty())
     });
     let listed = separated_list1(line_ending, choice_to_string);
     let head = r#"{"bevy_novelgame_dialog::writing::window_controller::choice::SetupChoice": ("#;
     let middle = r#"target_list: ["#;
     let last = r#"],),}"#;
-    let list_to_ron = map(listed, |x| format!("{head}{middle}{}{last}", x.concat()));
+    let list_to_ron = map(listed, |x| {
+        let targets = x.iter().map(|c| c.0.as_str()).collect::<String>();
+        if x.iter().any(|c| c.2) {
+            let conditions = x.iter().map(|c| c.1.as_str()).collect::<String>();
+            let conditioned = format!("{targets}],conditions: [{conditions}");
+            format!("{head}{middle}{conditioned}{last}")
+        } else {
+            format!("{head}{middle}{targets}{last}")
+        }
+    });
     let parsed = map(list_to_ron, |s| {
         ParsedOrder::OrderWrapper(Order::ThroghEvent { ron: s })
     })(input);
     parsed
 }
 
+fn choice_condition(input: &str) -> IResult<&str, (&str, &str)> {
+    let key = alt((tag("show_if"), tag("enable_if")));
+    let condition = separated_pair(key, char('='), is_not("}\r\n"));
+    let call = map(tag("call"), |k| (k, ""));
+    delimited(char('{'), alt((condition, call)), char('}'))(input)
+}
+
 #[cfg(test)]
 mod parse_bds_tests {
     use super::*;
 
-    const HELLO: &[Order] = &[
-        Order::Type { character: 'こ' },
-        Order::Type { character: 'ん' },
-        Order::Type { character: 'に' },
-        Order::Type { character: 'ち' },
-        Order::Type { character: 'は' },
-        Order::CarriageReturn,
-        Order::Type { character: 'は' },
-        Order::Type { character: 'じ' },
-        Order::Type { character: 'め' },
-        Order::Type { character: 'ま' },
-        Order::Type { character: 'し' },
-        Order::Type { character: 'て' },
-    ];
-
-    const ILL: &[Order] = &[
-        Order::Type { character: 'こ' },
-        Order::Type { character: 'の' },
-        Order::Type { character: '家' },
-        Order::Type { character: 'の' },
-        Order::Type { character: '主' },
-        Order::Type { character: '人' },
-        Order::Type { character: 'は' },
-        Order::Type { character: '病' },
-        Order::Type { character: '気' },
-        Order::Type { character: 'で' },
-        Order::Type { character: 'す' },
-    ];
+    fn text(s: &str) -> Order {
+        Order::Text {
+            text: s.to_string(),
+        }
+    }
+
+    fn hello() -> Vec<Order> {
+        vec![
+            text("こんにちは"),
+            Order::CarriageReturn,
+            text("はじめまして"),
+        ]
+    }
+
+    fn ill() -> Vec<Order> {
+        vec![text("この家の主人は病気です")]
+    }
 
     #[test]
     fn test_hello_br() {
-        let hello_po = HELLO
-            .iter()
-            .map(|o| ParsedOrder::OrderWrapper(o.clone()))
-            .collect::<Vec<_>>();
-        assert_eq!(parse_bds("こんにちは<br />はじめまして"), hello_po);
+        assert_eq!(read_bds("こんにちは<br />はじめまして")[""], hello());
     }
 
     #[test]
     fn test_hello_double_space_end() {
-        let hello_vec = HELLO.into();
+        let hello_vec = hello();
         assert_eq!(
             read_bds("こんにちは  \r\nはじめまして"),
             HashMap::from([("".to_string(), hello_vec)])
@@ -348,10 +433,8 @@ mod parse_bds_tests {
 
     #[test]
     fn test_h1() {
-        let sectioned_phrase = HashMap::from([
-            ("".to_string(), HELLO.into()),
-            ("二つ目".to_string(), ILL.into()),
-        ]);
+        let sectioned_phrase =
+            HashMap::from([("".to_string(), hello()), ("二つ目".to_string(), ill())]);
         assert_eq!(
             read_bds("こんにちは<br>はじめまして<h1>二つ目</h1>この家の主人は病気です"),
             sectioned_phrase
@@ -360,10 +443,8 @@ mod parse_bds_tests {
 
     #[test]
     fn test_under_line() {
-        let sectioned_phrase = HashMap::from([
-            ("".to_string(), HELLO.into()),
-            ("二つ目".to_string(), ILL.into()),
-        ]);
+        let sectioned_phrase =
+            HashMap::from([("".to_string(), hello()), ("二つ目".to_string(), ill())]);
         let read =
             read_bds("こんにちは<br css='';/>はじめまして\n二つ目\n======\nこの家の主人は病気です");
         assert_eq!(read, sectioned_phrase);
@@ -371,10 +452,8 @@ mod parse_bds_tests {
 
     #[test]
     fn test_sharp_head() {
-        let sectioned_phrase = HashMap::from([
-            ("".to_string(), HELLO.into()),
-            ("二つ目".to_string(), ILL.into()),
-        ]);
+        let sectioned_phrase =
+            HashMap::from([("".to_string(), hello()), ("二つ目".to_string(), ill())]);
         let read =
             read_bds("こんにちは    \r\nはじめまして\r\n\r\n# 二つ目\r\nこの家の主人は病気です");
         assert_eq!(read, sectioned_phrase);
@@ -383,12 +462,7 @@ mod parse_bds_tests {
     #[test]
     fn test_double_endline() {
         let pf = &[Order::PageFeed];
-        let paged_phrase = [HELLO, pf, ILL]
-            .into_iter()
-            .map(|x| x.iter())
-            .flatten()
-            .map(|x| x.clone());
-        let vec_pp = paged_phrase.collect::<Vec<Order>>();
+        let vec_pp = [hello(), pf.to_vec(), ill()].concat();
         let read = read_bds("こんにちは    \r\nはじめまして\r\n\r\nこの家の主人は病気です");
         assert_eq!(read, HashMap::from([("".to_string(), vec_pp)]));
     }
@@ -396,12 +470,7 @@ mod parse_bds_tests {
     #[test]
     fn test_end_p_tag() {
         let pf = &[Order::PageFeed];
-        let paged_phrase = [HELLO, pf, ILL]
-            .into_iter()
-            .map(|x| x.iter())
-            .flatten()
-            .map(|x| x.clone());
-        let vec_pp = paged_phrase.collect::<Vec<Order>>();
+        let vec_pp = [hello(), pf.to_vec(), ill()].concat();
         let read = read_bds("<p>こんにちは    \r\nはじめまして</p>この家の主人は病気です");
         assert_eq!(read, HashMap::from([("".to_string(), vec_pp)]));
     }
@@ -411,12 +480,7 @@ mod parse_bds_tests {
         let script = &[Order::ThroghEvent {
             ron: "test".to_string(),
         }];
-        let with_script = [HELLO, script, ILL]
-            .into_iter()
-            .map(|x| x.iter())
-            .flatten()
-            .map(|x| x.clone());
-        let vec_ws = with_script.collect::<Vec<Order>>();
+        let vec_ws = [hello(), script.to_vec(), ill()].concat();
         let read =
             read_bds("こんにちは    \r\nはじめまして<script>test</script>この家の主人は病気です");
         assert_eq!(read, HashMap::from([("".to_string(), vec_ws)]));
@@ -425,18 +489,22 @@ mod parse_bds_tests {
     #[test]
     fn test_useless_tag() {
         let useless_taged = vec![
-            ParsedOrder::OrderWrapper(Order::Type { character: 'a' }),
-            ParsedOrder::OrderWrapper(Order::Type { character: 'a' }),
-            ParsedOrder::OrderWrapper(Order::Type { character: 'b' }),
-            ParsedOrder::OrderWrapper(Order::Type { character: 'c' }),
-            ParsedOrder::OrderWrapper(Order::Type { character: 'd' }),
-            ParsedOrder::OrderWrapper(Order::Type { character: '<' }),
-            ParsedOrder::OrderWrapper(Order::Type { character: 'a' }),
-            ParsedOrder::OrderWrapper(Order::Type { character: 'b' }),
-            ParsedOrder::OrderWrapper(Order::Type { character: '\\' }),
-            ParsedOrder::OrderWrapper(Order::Type { character: '>' }),
+            ParsedOrder::Char('a'),
+            ParsedOrder::Char('a'),
+            ParsedOrder::Char('b'),
+            ParsedOrder::Char('c'),
+            ParsedOrder::Char('d'),
+            ParsedOrder::Char('<'),
+            ParsedOrder::Char('a'),
+            ParsedOrder::Char('b'),
+            ParsedOrder::Char('\\'),
+            ParsedOrder::Char('>'),
         ];
         assert_eq!(parse_bds("a<abc>abcd\\<ab\\\\>"), useless_taged);
+        assert_eq!(
+            read_bds("a<abc>abcd\\<ab\\\\>")[""],
+            vec![text("aabcd<ab\\>")]
+        );
     }
 
     #[test]
@@ -460,6 +528,76 @@ mod parse_bds_tests {
         assert_eq!(parse_bds("(abc \"def\")"), vec![link]);
     }
 
+    #[test]
+    fn test_call_command() {
+        let command = ParsedOrder::OrderWrapper(Order::Command {
+            name: "shake_camera".to_string(),
+            args: vec![
+                ("power".to_string(), "3".to_string()),
+                ("target".to_string(), "\"Main \\\"Cam\\\"\"".to_string()),
+            ],
+        });
+        assert_eq!(
+            parse_bds("[@shake_camera power=3 target=\"Main \\\"Cam\\\"\"]"),
+            vec![command]
+        );
+        let unit = ParsedOrder::OrderWrapper(Order::Command {
+            name: "wait".to_string(),
+            args: vec![],
+        });
+        assert_eq!(parse_bds("[@wait]"), vec![unit]);
+    }
+
+    #[test]
+    fn test_echo_variable() {
+        let parsed = parse_bds("僕は${hero}。");
+        let expected = vec![
+            ParsedOrder::Char('僕'),
+            ParsedOrder::Char('は'),
+            ParsedOrder::OrderWrapper(Order::Variable {
+                name: "hero".to_string(),
+            }),
+            ParsedOrder::Char('。'),
+        ];
+        assert_eq!(parsed, expected);
+    }
+
+    #[test]
+    fn test_find_syntax_errors() {
+        let input = "こんにちは\n<script>{\"a\": ()}\n[@wait";
+        assert_eq!(
+            find_syntax_errors(input),
+            vec![
+                (16, "<script>{\"a\": ()}".to_string()),
+                (34, "[@wait".to_string())
+            ]
+        );
+        assert_eq!(read_bds(input)[""], vec![text("こんにちは")]);
+    }
+
+    #[test]
+    fn test_find_includes() {
+        let script = "abc<!-- include: common/greetings.md#Hello -->def<!-- note -->";
+        assert_eq!(
+            find_includes(script),
+            vec![(3..46, "common/greetings.md#Hello".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_extract_section() {
+        let script = "intro\n# Hello\nこんにちは\n# Bye\nさようなら\n";
+        assert_eq!(
+            extract_section(script, "Hello"),
+            Some("こんにちは\n".to_string())
+        );
+        assert_eq!(
+            extract_section(script, "Bye"),
+            Some("さようなら\n".to_string())
+        );
+        assert_eq!(extract_section(script, "None"), None);
+    }
+
     #[test]
     fn test_choice() {
         let ron = "{\"bevy_novelgame_dialog::writing::window_controller::choice::SetupChoice\": (target_list: [(\"efg\", \"{\\\"bevy_novelgame_dialog::writing::events::bds::LoadBds\\\": (path: \\\"abc\\\",target_name: \\\"def\\\",),}\"),(\"nop\", \"{\\\"bevy_novelgame_dialog::writing::events::bds::LoadBds\\\": (path: \\\"hij\\\",target_name: \\\"klm\\\",),}\"),],),}";
@@ -471,4 +609,52 @@ mod parse_bds_tests {
             vec![link]
         );
     }
+
+    #[test]
+    fn test_choice_condition() {
+        let ron = "{\"bevy_novelgame_dialog::writing::window_controller::choice::SetupChoice\": (target_list: [(\"efg\", \"{\\\"bevy_novelgame_dialog::writing::events::bds::LoadBds\\\": (path: \\\"abc\\\",target_name: \\\"def\\\",),}\"),(\"nop\", \"{\\\"bevy_novelgame_dialog::writing::events::bds::LoadBds\\\": (path: \\\"hij\\\",target_name: \\\"klm\\\",),}\"),],conditions: [(show_if: \"has_key\", enable_if: \"gold>=10\",),(show_if: \"\", enable_if: \"\",),],),}";
+        let link = ParsedOrder::OrderWrapper(Order::ThroghEvent {
+            ron: ron.to_string(),
+        });
+        assert_eq!(
+            parse_bds(
+                "* efg(abc \"def\"){show_if=has_key}{enable_if=gold>=10}\n* nop(hij \"klm\")\n"
+            ),
+            vec![link]
+        );
+    }
+
+    #[test]
+    fn test_choice_call() {
+        let ron = "{\"bevy_novelgame_dialog::writing::window_controller::choice::SetupChoice\": (target_list: [(\"efg\", \"{\\\"bevy_novelgame_dialog::writing::events::bds::CallBds\\\": (path: \\\"abc\\\",target_name: \\\"def\\\",),}\"),(\"nop\", \"{\\\"bevy_novelgame_dialog::writing::events::bds::LoadBds\\\": (path: \\\"hij\\\",target_name: \\\"klm\\\",),}\"),],conditions: [(show_if: \"has_key\", enable_if: \"\",),(show_if: \"\", enable_if: \"\",),],),}";
+        let link = ParsedOrder::OrderWrapper(Order::ThroghEvent {
+            ron: ron.to_string(),
+        });
+        assert_eq!(
+            parse_bds("* efg(abc \"def\"){call}{show_if=has_key}\n* nop(hij \"klm\")\n"),
+            vec![link]
+        );
+    }
+
+    // READMEの例がbasic.csvを通して読めることを確かめます。
+    #[test]
+    fn test_readme_choices() {
+        let readme = include_str!("../../README.md");
+        let template = include_str!("../ui_templates/assets/scripts/basic.csv");
+        let example = |key: &str| {
+            let line = readme.lines().find(|l| l.contains(key)).unwrap();
+            let start = line.find(&format!("`* [{key}")).unwrap() + 1;
+            let end = start + line[start..].find('`').unwrap();
+            replace_by_template(format!("{}\n", &line[start..end]), template).unwrap()
+        };
+        let door = r##"{"bevy_novelgame_dialog::writing::window_controller::choice::SetupChoice": (target_list: [("Open the door", "{\"bevy_novelgame_dialog::writing::events::bds::LoadBds\": (path: \"house.md#Door\",target_name: \"Main Box\",),}"),],conditions: [(show_if: "has_key", enable_if: "hp>0",),],),}"##;
+        let shop = r##"{"bevy_novelgame_dialog::writing::window_controller::choice::SetupChoice": (target_list: [("Shop", "{\"bevy_novelgame_dialog::writing::events::bds::CallBds\": (path: \"shop.md#Menu\",target_name: \"Main Box\",),}"),],),}"##;
+        let choice = |ron: &str| {
+            vec![ParsedOrder::OrderWrapper(Order::ThroghEvent {
+                ron: ron.to_string(),
+            })]
+        };
+        assert_eq!(parse_bds(&example("Open the door")), choice(door));
+        assert_eq!(parse_bds(&example("Shop")), choice(shop));
+    }
 }
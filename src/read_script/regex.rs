@@ -6,33 +6,78 @@ use nom::multi::*;
 use nom::sequence::*;
 use nom::*;
 use regex::Regex;
+use std::borrow::Cow;
+use thiserror::Error;
+
+/// A template row that can not be used. `line` is the 1-based line the row starts on.
+#[derive(Debug, Error)]
+pub(crate) enum TemplateError {
+    #[error("row at line {line} has {count} columns, expected 2")]
+    ColumnCount { line: usize, count: usize },
+    #[error("row at line {line} has an invalid pattern: {source}")]
+    Regex { line: usize, source: regex::Error },
+}
+
+// テンプレートの1行です。fromに当たった部分をtoで置き換えます。
+#[derive(Debug, Clone)]
+pub(crate) struct TemplateRule {
+    pub from: Regex,
+    pub to: String,
+}
 
-//エラー処理がちょっと雑
 pub(crate) fn replace_by_template<S1: AsRef<str>, S2: AsRef<str>>(
     input: S1,
     template: S2,
+) -> Result<String, TemplateError> {
+    let rules = read_template(template.as_ref())?;
+    Ok(apply_rules(input.as_ref(), &rules))
+}
+
+// 当たらなかった行では文字列を作り直しません。
+pub(crate) fn apply_rules<'a, I: IntoIterator<Item = &'a TemplateRule>>(
+    input: &str,
+    rules: I,
 ) -> String {
-    let template_vec = read_template(template.as_ref());
-    template_vec
+    let mut replaced = Cow::Borrowed(input);
+    for rule in rules {
+        let next = match rule.from.replace_all(&replaced, rule.to.as_str()) {
+            Cow::Owned(s) => Some(s),
+            Cow::Borrowed(_) => None,
+        };
+        if let Some(s) = next {
+            replaced = Cow::Owned(s);
+        }
+    }
+    replaced.into_owned()
+}
+
+// 空行は読み飛ばし、それ以外で2列でない行や読めない正規表現はエラーにします。
+pub(crate) fn read_template(input: &str) -> Result<Vec<TemplateRule>, TemplateError> {
+    parse_csv_with_lines(input)
         .into_iter()
-        .fold(input.as_ref().to_string(), |base, (from, to)| {
-            Regex::new(&from)
-                .unwrap()
-                .replace_all(&base, to)
-                .to_string()
+        .filter(|(_, v)| !(v.len() == 1 && v[0].is_empty()))
+        .map(|(line, v)| match <[String; 2]>::try_from(v) {
+            Ok([from, to]) => Regex::new(&from)
+                .map(|from| TemplateRule { from, to })
+                .map_err(|source| TemplateError::Regex { line, source }),
+            Err(v) => Err(TemplateError::ColumnCount {
+                line,
+                count: v.len(),
+            }),
         })
+        .collect()
 }
 
-fn read_template(input: &str) -> Vec<(String, String)> {
-    let base = parse_csv(input).into_iter();
-    base.filter_map(|mut v| {
-        let (v1, v2) = (v.pop()?, v.pop()?);
-        Some((v2, v1))
-    })
-    .collect()
+#[cfg(test)]
+fn parse_csv(input: &str) -> Vec<Vec<String>> {
+    parse_csv_with_lines(input)
+        .into_iter()
+        .map(|(_, v)| v)
+        .collect()
 }
 
-fn parse_csv(input: &str) -> Vec<Vec<String>> {
+// 各行の値と、その行が始まる1始まりの行番号を返します。
+fn parse_csv_with_lines(input: &str) -> Vec<(usize, Vec<String>)> {
     let last_char = input.chars().last();
     let is_eof_endline = last_char == Some('\n') || last_char == Some('\r');
     let added_last_line = if is_eof_endline {
@@ -40,8 +85,17 @@ fn parse_csv(input: &str) -> Vec<Vec<String>> {
     } else {
         [input, "\r\n"].concat()
     };
-    let (_, csv) = many0(a_row)(added_last_line.as_str()).unwrap_or_default();
-    csv.into_iter().filter(|v| !v.is_empty()).collect()
+    let mut rows = Vec::new();
+    let mut rest = added_last_line.as_str();
+    while let Ok((rem, row)) = a_row(rest) {
+        if rem.len() == rest.len() {
+            break;
+        }
+        let consumed = &added_last_line[..added_last_line.len() - rest.len()];
+        rows.push((consumed.matches('\n').count() + 1, row));
+        rest = rem;
+    }
+    rows.into_iter().filter(|(_, v)| !v.is_empty()).collect()
 }
 
 fn a_row(input: &str) -> IResult<&str, Vec<String>> {
@@ -169,6 +223,20 @@ $t
     "bevy_novelgame_dialog::writing::events::bds::features::ChangeFontSize": (
         size: 27.0,
 )}</script>お"#;
-        assert_eq!(replace_by_template(base, csv), replaced);
+        assert_eq!(replace_by_template(base, csv).unwrap(), replaced);
+    }
+
+    #[test]
+    fn test_invalid_template() {
+        let csv = "a,b\n\n\"(\",c\nd,e,f\n";
+        let Err(TemplateError::Regex { line, .. }) = read_template(csv) else {
+            panic!("an unclosed group should be an error");
+        };
+        assert_eq!(line, 3);
+        let Err(TemplateError::ColumnCount { line, count }) = read_template("a,b\nd,e,f\n") else {
+            panic!("three columns should be an error");
+        };
+        assert_eq!((line, count), (2, 3));
+        assert_eq!(read_template("\na,b\n").map(|v| v.len()).ok(), Some(1));
     }
 }
@@ -60,6 +60,7 @@ fn setup_choice_images(
         );
         let cb = ChoiceButton {
             target_box_name: "Choice Box".to_string(),
+            target_box: None,
             sort_number: i,
         };
         commands.spawn((button_sprite_bundle, cb));
@@ -127,6 +127,7 @@ fn waiting_sprite_setup(
         AnimationTimer(Timer::from_seconds(0.5, TimerMode::Repeating)),
         WaitingIcon {
             target_box_name: "Main Box".to_string(),
+            target_box: None,
             wait_for: vec![WaitTarget::SimpleWaiting],
         },
         WaitingSprite,
@@ -162,6 +163,7 @@ fn wait_feeding_sprite_setup(
         AnimationTimer(Timer::from_seconds(0.5, TimerMode::Repeating)),
         WaitingIcon {
             target_box_name: "Main Box".to_string(),
+            target_box: None,
             wait_for: vec![WaitTarget::Feeding],
         },
         WaitingSprite,
@@ -41,7 +41,8 @@ pub struct RoseStyleUIPlugin {
     pub button_size: Vec2,
     /// Specifies the size of the name plate.
     pub name_plate_size: Vec2,
-    /// Specifies the maximum number of choice buttons.
+    /// Specifies the number of choice buttons spawned at startup.
+    /// More buttons are added below them when a choice has more options.
     pub max_button_index: usize,
     /// Specifies the font size.
     pub font_size: f32,
@@ -127,6 +128,7 @@ fn open_message(
             size_by_regulars: HashMap::from([("[[:alpha:]]".to_string(), 1.2)]),
             text_base_size: config.font_size,
             font_color: TextColor(Color::srgb(0.9, 0.9, 0.9)),
+            ..default()
         };
         let text_area_x = -config.box_size.x / 2.0 + config.box_pos.x + 80.0;
         let text_area_y = config.box_size.y / 2.0 + config.box_pos.y + 100.0;
@@ -195,6 +197,9 @@ fn open_message(
                 button_text_areas: tac_list,
                 background_scaling_per_button: Vec2::new(0.0, config.button_size.y + 40.0),
                 background_scaling_anchor: Anchor::TopCenter,
+                overflow: ChoiceOverflow::Extend {
+                    step: Vec2::new(0.0, -(config.button_size.y + 40.0)),
+                },
                 ..default()
             },
             ..default()
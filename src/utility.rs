@@ -22,24 +22,6 @@ pub(crate) fn find_by_regex<T: Clone, S: AsRef<str>>(s: S, base: &HashMap<String
     reg.and_then(|r| base.get(r)).cloned()
 }
 
-pub(crate) fn choice_font<R: AsRef<[TextFont]>>(
-    list: &R,
-    target: char,
-    fonts: &Assets<Font>,
-) -> Option<TextFont> {
-    let finded = list
-        .as_ref()
-        .iter()
-        .find(|tf| {
-            fonts
-                .get(&tf.font)
-                .map(|f| glyph_exists_in_font(f.clone(), target))
-                .unwrap_or(false)
-        })
-        .cloned();
-    finded.or(list.as_ref().iter().last().cloned())
-}
-
 #[allow(dead_code)]
 pub(crate) fn choice_font_with_index<R: AsRef<[Handle<Font>]>>(
     list: &R,
@@ -49,7 +31,7 @@ pub(crate) fn choice_font_with_index<R: AsRef<[Handle<Font>]>>(
     let finded = list.as_ref().iter().enumerate().find(|(_, h)| {
         fonts
             .get(*h)
-            .map(|f| glyph_exists_in_font(f.clone(), target))
+            .map(|f| glyph_exists_in_font(f, target))
             .unwrap_or(false)
     });
     finded.map(|(i, f)| (i, f.clone())).or(list
@@ -59,8 +41,8 @@ pub(crate) fn choice_font_with_index<R: AsRef<[Handle<Font>]>>(
         .next_back())
 }
 
-fn glyph_exists_in_font(font: Font, target: char) -> bool {
-    let Some(buffer) = get_glyph_buffer(&font, target) else {
+fn glyph_exists_in_font(font: &Font, target: char) -> bool {
+    let Some(buffer) = get_glyph_buffer(font, target) else {
         return false;
     };
     buffer
@@ -0,0 +1,293 @@
+use bevy::{ecs::system::SystemParam, prelude::*};
+
+use super::input::{DisabledChoice, PushedByCommand, Selected, WaitInputGo};
+use super::text_controller::typing_animations::TypingStyle;
+use super::text_controller::{MessageTextChar, MessageTextLine, TypingTimer};
+use super::window_controller::sinkdown::GoSinking;
+use super::*;
+use crate::read_script::{load_script, split_path_and_section};
+
+/// Controls open dialog boxes from game code.
+///
+/// Every method takes the entity of a dialog box, as returned by
+/// [`OpenDialogCommandsExt::open_dialog`]. Changes are applied with [`Commands`].
+#[derive(SystemParam)]
+pub struct DialogCommands<'w, 's> {
+    commands: Commands<'w, 's>,
+    boxes: Query<'w, 's, (Entity, &'static DialogBox, &'static DialogBoxPhase)>,
+    backgrounds: Query<'w, 's, (Entity, &'static DialogBoxBackground)>,
+}
+
+impl DialogCommands<'_, '_> {
+    /// Opens a dialog box and returns its entity.
+    ///
+    /// If `target` is `None`, the box is built on the [`DialogBoxBackground`] named
+    /// `writing_name` if there is one, or on a new entity.
+    pub fn open(&mut self, config: OpenDialog) -> Entity {
+        let target = config.target.or_else(|| {
+            self.backgrounds
+                .iter()
+                .find(|x| x.1.writing_name == config.writing_name)
+                .map(|x| x.0)
+        });
+        self.commands.open_dialog(OpenDialog { target, ..config })
+    }
+
+    /// Returns the entity of the dialog box named `name`.
+    pub fn find(&self, name: &str) -> Option<Entity> {
+        self.boxes.iter().find(|x| x.1.name == name).map(|x| x.0)
+    }
+
+    /// Returns the phase of `dialog_box`, or `None` if it is not open.
+    pub fn current_phase(&self, dialog_box: Entity) -> Option<DialogBoxPhase> {
+        self.boxes.get(dialog_box).ok().map(|x| *x.2)
+    }
+
+    /// Returns `true` if `dialog_box` is waiting for the player.
+    pub fn is_waiting(&self, dialog_box: Entity) -> bool {
+        self.current_phase(dialog_box) == Some(DialogBoxPhase::WaitingAction)
+    }
+
+    /// Acts as if the player pressed the confirm button on `dialog_box`.
+    ///
+    /// Typing is skipped first if it is still in progress, and a choice box confirms
+    /// the selected button.
+    pub fn advance(&mut self, dialog_box: Entity) {
+        if self.current_phase(dialog_box) == Some(DialogBoxPhase::Typing) {
+            self.skip_typing(dialog_box);
+            return;
+        }
+        self.commands.queue(move |w: &mut World| {
+            let mut ta_query = w.query_filtered::<(Entity, &ChildOf, Has<Selected>), (
+                With<TextArea>,
+                With<WaitInputGo>,
+                Without<Pending>,
+                Without<DisabledChoice>,
+            )>();
+            let target = ta_query
+                .iter(w)
+                .filter(|x| x.1.parent() == dialog_box)
+                .max_by_key(|x| x.2)
+                .map(|x| x.0);
+            if let Some(ta_entity) = target {
+                w.entity_mut(ta_entity).insert(PushedByCommand);
+            }
+        });
+    }
+
+    /// Shows every character already written in `dialog_box` without going to the next.
+    pub fn skip_typing(&mut self, dialog_box: Entity) {
+        self.commands.queue(move |w: &mut World| {
+            let mut char_query = w.query_filtered::<(Entity, &ChildOf), With<MessageTextChar>>();
+            let mut line_query = w.query_filtered::<&ChildOf, With<MessageTextLine>>();
+            let mut area_query = w.query_filtered::<&ChildOf, With<TextArea>>();
+            let targets = char_query
+                .iter(w)
+                .filter(|(_, c_parent)| {
+                    line_query
+                        .get(w, c_parent.parent())
+                        .and_then(|l_parent| area_query.get(w, l_parent.parent()))
+                        .is_ok_and(|ta_parent| ta_parent.parent() == dialog_box)
+                })
+                .map(|x| x.0)
+                .collect::<Vec<_>>();
+            for entity in targets {
+                let mut char_entity = w.entity_mut(entity);
+                if let Some(mut tf) = char_entity.get_mut::<Transform>() {
+                    tf.scale = Vec3::ONE;
+                }
+                if let Some(mut vis) = char_entity.get_mut::<Visibility>() {
+                    *vis = Visibility::Inherited;
+                }
+                if let Some(mut tt) = char_entity.get_mut::<TypingTimer>() {
+                    let rem = tt.timer.duration();
+                    tt.timer.tick(rem);
+                }
+                char_entity.insert(TypingStyle::Typed);
+            }
+            let mut batched_query = w.query_filtered::<(Entity, &ChildOf), With<BatchedLine>>();
+            let lines = batched_query
+                .iter(w)
+                .filter(|(_, l_parent)| {
+                    area_query
+                        .get(w, l_parent.parent())
+                        .is_ok_and(|ta_parent| ta_parent.parent() == dialog_box)
+                })
+                .map(|x| x.0)
+                .collect::<Vec<_>>();
+            for entity in lines {
+                if let Some(mut line) = w.get_mut::<BatchedLine>(entity) {
+                    line.reveal_all();
+                }
+            }
+        });
+    }
+
+    /// Closes `dialog_box` with `sink_type`.
+    pub fn close(&mut self, dialog_box: Entity, sink_type: SinkDownType) {
+        let Ok((_, db, phase)) = self.boxes.get(dialog_box) else {
+            return;
+        };
+        if *phase == DialogBoxPhase::SinkingDown {
+            return;
+        }
+        self.commands.send_event(GoSinking {
+            writing_name: db.name.clone(),
+            sink_type,
+            target: Some(dialog_box),
+        });
+    }
+
+    /// Makes `dialog_box` read `path` next. A section can be given as `file.md#Section`,
+    /// and `#Section` moves to another section of the current script.
+    ///
+    /// Like a jump in the script, this discards the return points of `[call]`.
+    pub fn jump_to(&mut self, dialog_box: Entity, path: impl Into<String>) {
+        let (file, section) = split_path_and_section(path.into());
+        self.commands.queue(move |w: &mut World| {
+            let asset_server = w.resource::<AssetServer>().clone();
+            if let Some(mut ls) = w.get_mut::<LoadedScript>(dialog_box) {
+                if !file.is_empty() {
+                    ls.bds_handle_opt = Some(load_script(&asset_server, file, &ls.templates));
+                }
+                ls.target_section = section;
+                ls.order_list = None;
+                ls.section_len = 0;
+                ls.call_stack.clear();
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod control_tests {
+    use super::*;
+    use crate::writing::test_app::*;
+    use bevy::ecs::system::RunSystemOnce;
+
+    #[derive(Resource, Default)]
+    struct Opened(Option<Entity>);
+
+    #[test]
+    fn test_open_on_named_background() {
+        let mut app = test_app();
+        let background = app
+            .world_mut()
+            .spawn((
+                DialogBoxBackground {
+                    writing_name: "Main Box".to_string(),
+                },
+                Sprite::default(),
+            ))
+            .id();
+        app.init_resource::<Opened>().add_systems(
+            Update,
+            |mut dc: DialogCommands, mut opened: ResMut<Opened>| {
+                if opened.0.is_none() {
+                    opened.0 = Some(dc.open(raw_dialog(vec![text("a")])));
+                }
+            },
+        );
+        run(&mut app, 3);
+        assert_eq!(app.world().resource::<Opened>().0, Some(background));
+        assert!(app.world().get::<DialogBox>(background).is_some());
+        let boxes = app
+            .world_mut()
+            .query::<&DialogBox>()
+            .iter(app.world())
+            .count();
+        assert_eq!(boxes, 1);
+    }
+
+    fn open_on(app: &mut App, config: OpenDialog) -> Entity {
+        let dialog_box = app.world_mut().spawn_empty().id();
+        app.world_mut().send_event(OpenDialog {
+            target: Some(dialog_box),
+            ..config
+        });
+        dialog_box
+    }
+
+    fn script_path(app: &App, dialog_box: Entity) -> Option<String> {
+        let ls = app.world().get::<LoadedScript>(dialog_box).unwrap();
+        let handle = ls.bds_handle_opt.as_ref()?;
+        handle.path().map(|p| p.without_label().to_string())
+    }
+
+    #[test]
+    fn test_jump_to() {
+        let mut app = test_app();
+        let dialog_box = open_on(&mut app, raw_dialog(vec![]));
+        run(&mut app, 3);
+        let jump = move |mut dc: DialogCommands| dc.jump_to(dialog_box, "a.md#First");
+        app.world_mut().run_system_once(jump).unwrap();
+        assert_eq!(script_path(&app, dialog_box).as_deref(), Some("a.md"));
+        // ファイルを書かなければ、同じスクリプトの別のsectionに移ります。
+        let jump = move |mut dc: DialogCommands| dc.jump_to(dialog_box, "#Second");
+        app.world_mut().run_system_once(jump).unwrap();
+        assert_eq!(script_path(&app, dialog_box).as_deref(), Some("a.md"));
+        let ls = app.world().get::<LoadedScript>(dialog_box).unwrap();
+        assert_eq!(ls.target_section, "Second");
+        assert!(ls.order_list.is_none());
+    }
+
+    fn typing_box(app: &mut App, orders: Vec<Order>) -> Entity {
+        let area = TextAreaConfig {
+            // 打ち始めた文字を、消し込みが終わらないうちに飛ばします。
+            typing_timing: TypingTiming::ByChar { sec: 0.001 },
+            writing: WritingStyle::Wipe { sec: 100.0 },
+            ..font_area(app)
+        };
+        let dialog_box = open_on(
+            app,
+            OpenDialog {
+                text_area_configs: vec![area],
+                ..raw_dialog(orders)
+            },
+        );
+        run(app, 10);
+        dialog_box
+    }
+
+    fn advance(app: &mut App, dialog_box: Entity) {
+        let advance = move |mut dc: DialogCommands| dc.advance(dialog_box);
+        app.world_mut().run_system_once(advance).unwrap();
+    }
+
+    fn phase(app: &App, dialog_box: Entity) -> DialogBoxPhase {
+        *app.world().get::<DialogBoxPhase>(dialog_box).unwrap()
+    }
+
+    fn all_typed(app: &mut App) -> bool {
+        let mut chars = app
+            .world_mut()
+            .query_filtered::<Option<&TypingStyle>, With<MessageTextChar>>();
+        let styles = chars.iter(app.world()).collect::<Vec<_>>();
+        !styles.is_empty() && styles.iter().all(|s| matches!(s, Some(TypingStyle::Typed)))
+    }
+
+    #[test]
+    fn test_advance_skips_typing_first() {
+        let mut app = test_app();
+        // 改ページがなければ、打ち終わるまでTypingのままです。
+        let dialog_box = typing_box(&mut app, vec![text("あい")]);
+        assert_eq!(phase(&app, dialog_box), DialogBoxPhase::Typing);
+        assert!(!all_typed(&mut app));
+        advance(&mut app, dialog_box);
+        assert!(all_typed(&mut app));
+        let mut pushed = app.world_mut().query::<&PushedByCommand>();
+        assert_eq!(pushed.iter(app.world()).count(), 0);
+    }
+
+    #[test]
+    fn test_advance_skips_before_feeding() {
+        let mut app = test_app();
+        let dialog_box = typing_box(&mut app, vec![Order::PageFeed, text("あい")]);
+        assert_eq!(phase(&app, dialog_box), DialogBoxPhase::WaitingAction);
+        assert!(!all_typed(&mut app));
+        advance(&mut app, dialog_box);
+        run(&mut app, 3);
+        assert!(all_typed(&mut app));
+        assert_eq!(phase(&app, dialog_box), DialogBoxPhase::WaitingAction);
+    }
+}
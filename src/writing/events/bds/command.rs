@@ -0,0 +1,57 @@
+use bevy::{prelude::*, reflect::GetTypeRegistration};
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+/// Short names of script events, used by the `[@name key=value]` syntax in BDS scripts.
+///
+/// Values are written in RON as they are, so strings need quotes:
+/// `[@signal signal="Rabit_clap"]`.
+// スクリプトのローダーと共有するので、クローンしても同じ表を指します。
+#[derive(Resource, Default, Debug, Clone)]
+pub struct DialogCommandRegistry {
+    type_paths: Arc<RwLock<HashMap<String, String>>>,
+}
+
+impl DialogCommandRegistry {
+    /// Returns the type path registered as `name`.
+    pub fn type_path(&self, name: &str) -> Option<String> {
+        self.type_paths.read().ok()?.get(name).cloned()
+    }
+
+    // ReflectDeserializerが読めるronに直します。
+    pub(crate) fn to_ron(&self, name: &str, args: &[(String, String)]) -> Option<String> {
+        let path = self.type_path(name)?;
+        let fields = args
+            .iter()
+            .map(|(k, v)| format!("{k}: {v},"))
+            .collect::<String>();
+        Some(format!("{{\"{path}\": ({fields}),}}"))
+    }
+}
+
+/// Registers script events callable by a short name.
+pub trait DialogCommandAppExt {
+    /// Registers `T` for scripts and makes `[@name ...]` throw it.
+    fn register_dialog_command<T>(&mut self, name: impl Into<String>) -> &mut Self
+    where
+        T: Reflect + TypePath + GetTypeRegistration;
+}
+
+impl DialogCommandAppExt for App {
+    fn register_dialog_command<T>(&mut self, name: impl Into<String>) -> &mut Self
+    where
+        T: Reflect + TypePath + GetTypeRegistration,
+    {
+        self.register_type::<T>();
+        let registry = self
+            .world_mut()
+            .get_resource_or_init::<DialogCommandRegistry>()
+            .clone();
+        if let Ok(mut type_paths) = registry.type_paths.write() {
+            type_paths.insert(name.into(), T::type_path().to_string());
+        }
+        self
+    }
+}
@@ -1,19 +1,62 @@
+pub mod command;
+pub mod trigger;
 pub mod writing;
+pub use command::*;
+pub use trigger::*;
 pub use writing::*;
 
 use crate::read_script::*;
 use crate::writing::settings::configs::*;
 use crate::writing::settings::params::*;
 use crate::writing::window_controller::*;
-use bevy::prelude::*;
+use crate::writing::FinisClosingBox;
+use bevy::{
+    ecs::{event::EventCursor, system::SystemParam},
+    prelude::*,
+};
+use std::collections::HashMap;
 
 // Reflect登録必須。逆にEventは基本要らない
+// dialog_boxはスクリプトを読んでいたDialogBoxです。スクリプト以外から発行された場合はNoneになります。
 #[derive(Event)]
 pub struct BdsEvent {
     pub value: Box<dyn PartialReflect>,
+    pub dialog_box: Option<Entity>,
+    pub source: BdsSource,
+}
+
+/// Where a [`BdsEvent`] was thrown from.
+///
+/// Every field is left empty when the event was not sent by a dialog box.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BdsSource {
+    /// The name of the dialog box the event came from.
+    pub dialog_box_name: String,
+    /// The text area that was being typed into or waiting for input.
+    pub text_area: Option<Entity>,
+    /// The name of `text_area`.
+    pub text_area_name: String,
+    /// Set only for events written in a script.
+    pub location: Option<ScriptLocation>,
+}
+
+/// A position of an order in a BDS script.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScriptLocation {
+    /// The asset path of the script. Empty for orders given with `OpenDialog::raw_orders`.
+    pub path: String,
+    /// The section the orders were read from.
+    pub section: String,
+    /// The index of the order in the section.
+    pub index: usize,
 }
 
 impl BdsEvent {
+    // 発行元のDialogBoxが分かっていればそれと比べ、分からなければfallbackを返します。
+    pub fn is_for(&self, db_entity: Entity, fallback: bool) -> bool {
+        self.dialog_box.map_or(fallback, |e| e == db_entity)
+    }
+
     pub fn base<T: Default + Reflect>(&self) -> T {
         let mut my_data = <T>::default();
         my_data.apply(&*self.value);
@@ -29,8 +72,75 @@ impl BdsEvent {
     }
 }
 
+/// Reads [`BdsEvent`]s thrown from a specific dialog box.
+///
+/// Each dialog box has its own cursor, so reading the events of one box
+/// does not pass over the events of the others. The cursors of a box are
+/// dropped when it finishes closing or is despawned.
+#[derive(SystemParam)]
+pub struct BdsEventReader<'w, 's> {
+    events: Res<'w, Events<BdsEvent>>,
+    closed: EventReader<'w, 's, FinisClosingBox>,
+    removed: RemovedComponents<'w, 's, DialogBox>,
+    cursors: Local<'s, HashMap<Entity, EventCursor<BdsEvent>>>,
+    named_cursors: Local<'s, HashMap<String, EventCursor<BdsEvent>>>,
+}
+
+impl BdsEventReader<'_, '_> {
+    // 閉じた箱のカーソルは二度と使わないので捨てます。
+    fn forget_closed(&mut self) {
+        for closed in self.closed.read() {
+            self.cursors.remove(&closed.dialog_box);
+            self.named_cursors.remove(&closed.writing_name);
+        }
+        for entity in self.removed.read() {
+            self.cursors.remove(&entity);
+        }
+    }
+
+    /// Iterates over the events thrown from `db_entity`.
+    pub fn read_from(&mut self, db_entity: Entity) -> impl Iterator<Item = &BdsEvent> {
+        self.forget_closed();
+        self.cursors
+            .entry(db_entity)
+            .or_default()
+            .read(&self.events)
+            .filter(move |e| e.dialog_box == Some(db_entity))
+    }
+
+    /// Iterates over the events thrown from the dialog box named `db_name`.
+    pub fn read_from_named<'a>(
+        &'a mut self,
+        db_name: &'a str,
+    ) -> impl Iterator<Item = &'a BdsEvent> {
+        self.forget_closed();
+        if !self.named_cursors.contains_key(db_name) {
+            self.named_cursors
+                .insert(db_name.to_string(), EventCursor::default());
+        }
+        self.named_cursors
+            .get_mut(db_name)
+            .unwrap()
+            .read(&self.events)
+            .filter(move |e| e.dialog_box.is_some() && e.source.dialog_box_name == db_name)
+    }
+
+    /// Iterates over the events thrown from `db_entity` whose value is a `T`.
+    pub fn read_values_from<T: Default + Reflect + TypePath>(
+        &mut self,
+        db_entity: Entity,
+    ) -> impl Iterator<Item = (T, &BdsSource)> {
+        self.read_from(db_entity)
+            .filter_map(|e| e.get::<T>().map(|v| (v, &e.source)))
+    }
+}
+
 //-----
 
+/// Reads `path` in the dialog box the event was thrown from. Sent from outside
+/// of a script, it reads `path` in the dialog box named `target_name`.
+///
+/// Any return points left by [`CallBds`] are discarded.
 #[derive(Reflect, Default, Debug)]
 pub struct LoadBds {
     pub path: String,
@@ -39,7 +149,7 @@ pub struct LoadBds {
 
 pub(in crate::writing) fn load_bds(
     mut events: EventReader<BdsEvent>,
-    mut db_query: Query<(&DialogBox, &mut LoadedScript)>,
+    mut db_query: Query<(Entity, &DialogBox, &mut LoadedScript)>,
     asset_server: Res<AssetServer>,
 ) {
     for event_wrapper in events.read() {
@@ -48,18 +158,96 @@ pub(in crate::writing) fn load_bds(
             target_name: n,
         }) = event_wrapper.get::<LoadBds>()
         {
-            for (DialogBox { name: db_name }, mut ls) in &mut db_query {
-                if db_name == &n {
+            for (db_entity, DialogBox { name: db_name }, mut ls) in &mut db_query {
+                if event_wrapper.is_for(db_entity, db_name == &n) {
                     let (file, section) = split_path_and_section(&p);
-                    ls.bds_handle_opt = Some(asset_server.load(file));
+                    ls.bds_handle_opt = Some(load_script(&asset_server, file, &ls.templates));
                     ls.target_section = section;
                     ls.order_list = None;
+                    ls.section_len = 0;
+                    // jumpは呼び出しから抜けるので、戻り先は残しません。
+                    ls.call_stack.clear();
+                }
+            }
+        }
+    }
+}
+
+//-----
+
+/// Reads `path` like [`LoadBds`], and comes back to the next order with [`ReturnBds`].
+///
+/// An empty `target_name` means the dialog box the event was thrown from.
+#[derive(Reflect, Default, Debug)]
+#[reflect(Default)]
+pub struct CallBds {
+    pub path: String,
+    #[reflect(default)]
+    pub target_name: String,
+}
+
+/// Goes back to the order after the last [`CallBds`]. Does nothing outside of a call.
+#[derive(Reflect, Default, Debug)]
+#[reflect(Default)]
+pub struct ReturnBds {
+    #[reflect(default)]
+    pub target_name: String,
+}
+
+pub(in crate::writing) fn call_bds(
+    mut events: EventReader<BdsEvent>,
+    mut db_query: Query<(Entity, &DialogBox, &mut LoadedScript)>,
+    asset_server: Res<AssetServer>,
+) {
+    for event_wrapper in events.read() {
+        if let Some(CallBds {
+            path: p,
+            target_name: n,
+        }) = event_wrapper.get::<CallBds>()
+        {
+            for (db_entity, db, mut ls) in &mut db_query {
+                if !is_call_target(event_wrapper, db_entity, db, &n) {
+                    continue;
+                }
+                let return_point = ReturnPoint {
+                    bds_handle_opt: ls.bds_handle_opt.clone(),
+                    target_section: ls.target_section.clone(),
+                    order_list: ls.order_list.take(),
+                    section_len: ls.section_len,
+                };
+                ls.call_stack.push(return_point);
+                let (file, section) = split_path_and_section(&p);
+                ls.bds_handle_opt = Some(load_script(&asset_server, file, &ls.templates));
+                ls.target_section = section;
+                ls.section_len = 0;
+            }
+        }
+        if let Some(ReturnBds { target_name: n }) = event_wrapper.get::<ReturnBds>() {
+            for (db_entity, db, mut ls) in &mut db_query {
+                if !is_call_target(event_wrapper, db_entity, db, &n) {
+                    continue;
                 }
+                let Some(return_point) = ls.call_stack.pop() else {
+                    continue;
+                };
+                ls.bds_handle_opt = return_point.bds_handle_opt;
+                ls.target_section = return_point.target_section;
+                // 呼び出し位置が末尾だった場合も、読み込み直さないよう空のリストにします。
+                ls.order_list = Some(return_point.order_list.unwrap_or_default());
+                ls.section_len = return_point.section_len;
             }
         }
     }
 }
 
+fn is_call_target(event: &BdsEvent, db_entity: Entity, db: &DialogBox, target_name: &str) -> bool {
+    if target_name.is_empty() {
+        event.is_for(db_entity, false)
+    } else {
+        db.name == target_name
+    }
+}
+
 //-----
 
 #[derive(Reflect, Default, Debug)]
@@ -80,6 +268,8 @@ pub struct SimpleStringSignal {
 #[derive(Event, Default, Debug)]
 pub struct BdsSignal {
     pub signal: String,
+    pub dialog_box: Option<Entity>,
+    pub source: BdsSource,
 }
 
 pub(in crate::writing) fn send_bds_signal(
@@ -93,6 +283,8 @@ pub(in crate::writing) fn send_bds_signal(
         {
             signal_events.write(BdsSignal {
                 signal: base_signal.clone(),
+                dialog_box: event_wrapper.dialog_box,
+                source: event_wrapper.source.clone(),
             });
         }
     }
@@ -140,7 +332,7 @@ pub struct ChangeCurrentTextAreaInCurrentBox {
 
 pub(crate) fn change_current_text_area_in_current_box(
     mut commands: Commands,
-    db_query: Query<Entity, (With<DialogBox>, With<Current>)>,
+    db_query: Query<Has<Current>, With<DialogBox>>,
     ta_query: Query<(Entity, &TextArea, &ChildOf)>,
     mut events: EventReader<BdsEvent>,
 ) {
@@ -150,7 +342,11 @@ pub(crate) fn change_current_text_area_in_current_box(
         }) = event_wrapper.get::<ChangeCurrentTextAreaInCurrentBox>()
         {
             for (entity, text_area, parent) in &ta_query {
-                if db_query.single().ok() == Some(parent.parent()) {
+                let db_entity = parent.parent();
+                let Ok(is_current) = db_query.get(db_entity) else {
+                    continue;
+                };
+                if event_wrapper.is_for(db_entity, is_current) {
                     if ta_name == text_area.name {
                         commands.entity(entity).insert(Current);
                     } else {
@@ -189,3 +385,115 @@ pub(crate) fn change_current_writing(
         }
     }
 }
+
+#[cfg(test)]
+mod load_bds_tests {
+    use super::*;
+    use crate::writing::test_app::*;
+    use crate::writing::{OpenDialog, Order};
+
+    #[test]
+    fn test_jump_only_in_throwing_box() {
+        let mut app = test_app();
+        let jump = Order::Command {
+            name: "jump".to_string(),
+            args: vec![
+                ("path".to_string(), "\"missing.md\"".to_string()),
+                ("target_name".to_string(), "\"Main Box\"".to_string()),
+            ],
+        };
+        let jumping = app.world_mut().spawn_empty().id();
+        let waiting = app.world_mut().spawn_empty().id();
+        // 同じ名前の箱が二つあっても、jumpを投げた箱だけが読み直します。
+        app.world_mut().send_event(OpenDialog {
+            target: Some(jumping),
+            ..raw_dialog(vec![jump])
+        });
+        app.world_mut().send_event(OpenDialog {
+            target: Some(waiting),
+            ..raw_dialog(vec![])
+        });
+        run(&mut app, 5);
+        let loaded = |e| {
+            app.world()
+                .get::<LoadedScript>(e)
+                .unwrap()
+                .bds_handle_opt
+                .is_some()
+        };
+        assert!(loaded(jumping));
+        assert!(!loaded(waiting));
+    }
+}
+
+#[cfg(test)]
+mod bds_event_reader_tests {
+    use super::*;
+    use bevy::ecs::system::SystemState;
+
+    fn event(db: Entity, name: &str, n: u32) -> BdsEvent {
+        BdsEvent {
+            value: Box::new(n),
+            dialog_box: Some(db),
+            source: BdsSource {
+                dialog_box_name: name.to_string(),
+                ..default()
+            },
+        }
+    }
+
+    #[test]
+    fn test_read_each_box() {
+        let mut world = World::new();
+        world.init_resource::<Events<BdsEvent>>();
+        world.init_resource::<Events<FinisClosingBox>>();
+        let a = world.spawn_empty().id();
+        let b = world.spawn_empty().id();
+        world.send_event(event(a, "A", 1));
+        world.send_event(event(b, "B", 2));
+        world.send_event(event(a, "A", 3));
+        let mut state = SystemState::<BdsEventReader>::new(&mut world);
+        let mut reader = state.get_mut(&mut world);
+        let read = |r: &mut BdsEventReader, e| {
+            r.read_values_from::<u32>(e)
+                .map(|v| v.0)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(read(&mut reader, b), vec![2]);
+        assert_eq!(read(&mut reader, a), vec![1, 3]);
+        assert!(read(&mut reader, a).is_empty());
+        let named = reader.read_from_named("B").count();
+        assert_eq!(named, 1);
+        assert_eq!(reader.read_from_named("B").count(), 0);
+    }
+
+    #[test]
+    fn test_forget_closed_boxes() {
+        let mut world = World::new();
+        world.init_resource::<Events<BdsEvent>>();
+        world.init_resource::<Events<FinisClosingBox>>();
+        let name = |n: &str| DialogBox {
+            name: n.to_string(),
+        };
+        let a = world.spawn(name("A")).id();
+        let b = world.spawn(name("B")).id();
+        world.send_event(event(a, "A", 1));
+        world.send_event(event(b, "B", 2));
+        let mut state = SystemState::<BdsEventReader>::new(&mut world);
+        let mut reader = state.get_mut(&mut world);
+        assert_eq!(reader.read_from(a).count(), 1);
+        assert_eq!(reader.read_from(b).count(), 1);
+        assert_eq!(reader.read_from_named("A").count(), 1);
+        assert_eq!(reader.cursors.len(), 2);
+        assert_eq!(reader.named_cursors.len(), 1);
+        world.send_event(FinisClosingBox {
+            writing_name: "A".to_string(),
+            dialog_box: a,
+        });
+        world.despawn(b);
+        let mut reader = state.get_mut(&mut world);
+        reader.forget_closed();
+        assert!(reader.cursors.is_empty());
+        assert!(reader.named_cursors.is_empty());
+    }
+}
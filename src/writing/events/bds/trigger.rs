@@ -0,0 +1,97 @@
+use bevy::{prelude::*, reflect::GetTypeRegistration};
+
+use super::*;
+use crate::writing::PhaseSet;
+use std::marker::PhantomData;
+
+/// A script event of type `T` delivered to observers.
+///
+/// Events thrown from a dialog box are triggered on the box entity, so both
+/// `commands.entity(db).observe(..)` and `app.add_observer(..)` receive them.
+#[derive(Event, Debug, Clone)]
+pub struct BdsTrigger<T: Send + Sync + 'static> {
+    pub value: T,
+    pub dialog_box: Option<Entity>,
+    pub source: BdsSource,
+}
+
+/// Registers script events to be delivered as [`BdsTrigger`].
+pub trait BdsTriggerAppExt {
+    /// Registers `T` for scripts and triggers a [`BdsTrigger<T>`] for each `T` thrown.
+    ///
+    /// Calling it again for the same `T` does nothing.
+    fn add_bds_trigger<T>(&mut self) -> &mut Self
+    where
+        T: Default + Reflect + TypePath + GetTypeRegistration;
+}
+
+impl BdsTriggerAppExt for App {
+    fn add_bds_trigger<T>(&mut self) -> &mut Self
+    where
+        T: Default + Reflect + TypePath + GetTypeRegistration,
+    {
+        // 二度目を足すと同じ値が二回届くので、型ごとに一度だけ足します。
+        if self.world().contains_resource::<BdsTriggerAdded<T>>() {
+            return self;
+        }
+        self.insert_resource(BdsTriggerAdded::<T>(PhantomData))
+            .register_type::<T>()
+            .add_systems(Update, trigger_bds_event::<T>.in_set(PhaseSet::Progress))
+    }
+}
+
+#[derive(Resource)]
+struct BdsTriggerAdded<T: Send + Sync + 'static>(PhantomData<T>);
+
+fn trigger_bds_event<T: Default + Reflect + TypePath>(
+    mut commands: Commands,
+    mut events: EventReader<BdsEvent>,
+) {
+    for event_wrapper in events.read() {
+        let Some(value) = event_wrapper.get::<T>() else {
+            continue;
+        };
+        let trigger = BdsTrigger {
+            value,
+            dialog_box: event_wrapper.dialog_box,
+            source: event_wrapper.source.clone(),
+        };
+        match event_wrapper.dialog_box {
+            Some(db_entity) => commands.trigger_targets(trigger, db_entity),
+            None => commands.trigger(trigger),
+        }
+    }
+}
+
+#[cfg(test)]
+mod trigger_tests {
+    use super::*;
+
+    #[derive(Resource, Default)]
+    struct Triggered(Vec<String>);
+
+    #[test]
+    fn test_trigger_once_per_event() {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_event::<BdsEvent>()
+            .init_resource::<Triggered>()
+            .add_bds_trigger::<SimpleStringSignal>()
+            .add_bds_trigger::<SimpleStringSignal>()
+            .add_observer(
+                |trigger: Trigger<BdsTrigger<SimpleStringSignal>>,
+                 mut triggered: ResMut<Triggered>| {
+                    triggered.0.push(trigger.event().value.signal.clone());
+                },
+            );
+        app.world_mut().send_event(BdsEvent {
+            value: Box::new(SimpleStringSignal {
+                signal: "clap".to_string(),
+            }),
+            dialog_box: None,
+            source: BdsSource::default(),
+        });
+        app.update();
+        assert_eq!(app.world().resource::<Triggered>().0, vec!["clap"]);
+    }
+}
@@ -6,14 +6,22 @@ pub struct ChangeFontSize {
     pub size: f32,
 }
 
+#[allow(clippy::type_complexity)]
 pub(in crate::writing) fn change_font_size(
     mut events: EventReader<BdsEvent>,
-    mut ta_query: Query<&mut TypeTextConfig, (With<Current>, With<TextArea>)>,
+    mut ta_query: Query<(&mut TypeTextConfig, &ChildOf), (With<Current>, With<TextArea>)>,
+    db_query: Query<Has<Current>, With<DialogBox>>,
 ) {
     for event_wrapper in events.read() {
         if let Some(ChangeFontSize { size: s }) = event_wrapper.get::<ChangeFontSize>() {
-            if let Ok(mut config) = ta_query.single_mut() {
-                config.base_size = s;
+            for (mut config, parent) in &mut ta_query {
+                let db_entity = parent.parent();
+                let Ok(is_current) = db_query.get(db_entity) else {
+                    continue;
+                };
+                if event_wrapper.is_for(db_entity, is_current) {
+                    config.base_size = s;
+                }
             }
         }
     }
@@ -34,22 +42,33 @@ pub struct ForceFeedingCurrentBox;
 
 pub(in crate::writing) fn force_feeding_current_box(
     mut commands: Commands,
-    mut writing_query: Query<(Entity, &DialogBox, &mut DialogBoxPhase), With<Current>>,
-    text_area_query: Query<(&TextArea, &ChildOf)>,
+    mut writing_query: Query<(Entity, &DialogBox, &mut DialogBoxPhase, Has<Current>)>,
+    text_area_query: Query<(Entity, &TextArea, &ChildOf)>,
     mut events: EventReader<BdsEvent>,
 ) {
     for event_wrapper in events.read() {
         if event_wrapper.get::<ForceFeedingCurrentBox>().is_some() {
-            for (db_entity, db, mut phase) in &mut writing_query {
-                for (ta, ta_parent) in &text_area_query {
+            for (db_entity, db, mut phase, is_current) in &mut writing_query {
+                if !event_wrapper.is_for(db_entity, is_current) {
+                    continue;
+                }
+                for (ta_entity, ta, ta_parent) in &text_area_query {
                     if ta_parent.parent() == db_entity {
                         let iff = InputForFeeding {
                             writing_name: db.name.clone(),
                             text_area_name: ta.name.clone(),
                         };
-                        commands.queue(|w: &mut World| {
+                        let source = BdsSource {
+                            dialog_box_name: db.name.clone(),
+                            text_area: Some(ta_entity),
+                            text_area_name: ta.name.clone(),
+                            location: event_wrapper.source.location.clone(),
+                        };
+                        commands.queue(move |w: &mut World| {
                             w.send_event(BdsEvent {
                                 value: Box::new(iff),
+                                dialog_box: Some(db_entity),
+                                source,
                             });
                         });
                         *phase = DialogBoxPhase::WaitingAction;
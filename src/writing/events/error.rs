@@ -0,0 +1,51 @@
+use bevy::prelude::*;
+use thiserror::Error;
+
+/// A problem found in a BDS script.
+///
+/// Bad orders are reported with this event and skipped, so a broken script never stops the game.
+#[derive(Event, Error, Debug, Clone, PartialEq)]
+#[error("{path}#{section}:{line}:{column}: {kind}: {snippet}")]
+pub struct DialogScriptError {
+    pub kind: ScriptErrorKind,
+    /// The dialog box that was reading the script.
+    pub dialog_box: Option<Entity>,
+    /// The asset path of the script. Empty for orders given with `OpenDialog::raw_orders`.
+    pub path: String,
+    pub section: String,
+    /// 1-based line in the script after templates are applied. `0` if unknown.
+    pub line: usize,
+    /// 1-based column in characters. `0` if unknown.
+    pub column: usize,
+    /// The text that caused the error.
+    pub snippet: String,
+}
+
+/// What is wrong in a [`DialogScriptError`].
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum ScriptErrorKind {
+    /// A tag or command is not closed.
+    #[error("could not parse")]
+    Syntax,
+    /// The section to read does not exist.
+    #[error("missing section")]
+    MissingSection,
+    /// A template of the dialog box could not be loaded. The script is not read.
+    #[error("invalid template ({0})")]
+    InvalidTemplate(String),
+    /// A script event could not be read as RON.
+    #[error("invalid script event ({0})")]
+    InvalidEvent(String),
+    /// A `[@name ...]` command is not registered.
+    #[error("unknown command")]
+    UnknownCommand,
+}
+
+// 1始まりの行と文字数で数えた列を返します。
+pub(crate) fn line_and_column(input: &str, offset: usize) -> (usize, usize) {
+    let before = &input[..offset.min(input.len())];
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    let column = before[line_start..].chars().count() + 1;
+    (line, column)
+}
@@ -1,36 +1,64 @@
 pub mod bds;
+pub mod error;
 pub use bds::*;
+pub use error::*;
 
 use crate::writing::settings::configs::*;
 use crate::writing::settings::params::*;
 use bevy::prelude::*;
 
+/// Opens a dialog box.
+///
+/// Send it with [`OpenDialogCommandsExt::open_dialog`] to get the entity of the box.
 #[derive(Event)]
 pub struct OpenDialog {
     pub writing_name: String,
+    /// The entity the dialog box is built on.
+    ///
+    /// If `None`, an entity with a [`DialogBoxBackground`] named `writing_name` is used,
+    /// or a new one is spawned. An entity without a [`DialogBoxBackground`] is despawned
+    /// when the box is closed.
+    pub target: Option<Entity>,
     pub position: Vec2,
     pub popup: PopupType,
     pub wait_breaker: WaitBrakerStyle,
     pub script_path: String,
+    /// Templates applied to the script before it is parsed.
     pub template_path: Vec<String>,
     pub raw_orders: Option<Vec<Order>>,
     pub template_open_choice: ChoiceBoxConfig,
     pub text_area_configs: Vec<TextAreaConfig>,
     pub main_text_area_name: String,
+    /// What the box does when the section to read does not exist.
+    pub missing_section: MissingSectionPolicy,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Order {
-    Type { character: char },
+    // 続けて打つ文字をまとめたものです。
+    Text {
+        text: String,
+    },
     CarriageReturn,
     PageFeed,
-    ThroghEvent { ron: String },
+    ThroghEvent {
+        ron: String,
+    },
+    Command {
+        name: String,
+        args: Vec<(String, String)>,
+    },
+    // ScriptVariablesの値を文字として差し込みます。
+    Variable {
+        name: String,
+    },
 }
 
 impl Default for OpenDialog {
     fn default() -> Self {
         OpenDialog {
             writing_name: "Main Box".to_string(),
+            target: None,
             position: Vec2::new(0., 0.),
             popup: PopupType::Scale { sec: 0.8 },
             wait_breaker: WaitBrakerStyle::Auto { wait_sec: 1.5 },
@@ -40,14 +68,39 @@ impl Default for OpenDialog {
             template_open_choice: ChoiceBoxConfig::default(),
             text_area_configs: vec![TextAreaConfig::default()],
             main_text_area_name: "Main Area".to_string(),
+            missing_section: MissingSectionPolicy::default(),
         }
     }
 }
 
+/// Sends [`OpenDialog`] from [`Commands`].
+pub trait OpenDialogCommandsExt {
+    /// Opens a dialog box on `config.target` and returns its entity.
+    ///
+    /// If `target` is `None`, a new entity is reserved immediately, so it can be stored
+    /// and used to control the box before it is built. [`Commands`] cannot look up a
+    /// [`DialogBoxBackground`] by name; use [`DialogCommands::open`](crate::writing::DialogCommands::open) or send
+    /// [`OpenDialog`] as an event to build the box on a named background.
+    fn open_dialog(&mut self, config: OpenDialog) -> Entity;
+}
+
+impl OpenDialogCommandsExt for Commands<'_, '_> {
+    fn open_dialog(&mut self, config: OpenDialog) -> Entity {
+        let entity = config.target.unwrap_or_else(|| self.spawn_empty().id());
+        self.send_event(OpenDialog {
+            target: Some(entity),
+            ..config
+        });
+        entity
+    }
+}
+
 #[derive(Event, Debug)]
 pub struct ButtonIsSelected {
     pub writing_name: String,
+    pub dialog_box: Entity,
     pub text_area_name: String,
+    pub text_area: Entity,
     pub select_vector: SelectVector,
     pub select_number: usize,
 }
@@ -55,10 +108,50 @@ pub struct ButtonIsSelected {
 #[derive(Event)]
 pub struct ButtonIsPushed {
     pub writing_name: String,
+    pub dialog_box: Entity,
     pub text_area_name: String,
+    pub text_area: Entity,
 }
 
 #[derive(Event)]
 pub struct FinisClosingBox {
     pub writing_name: String,
+    pub dialog_box: Entity,
+}
+
+/// Sent when a dialog box moves to the next stage of its life.
+#[derive(Event, Debug, Clone, PartialEq)]
+pub struct DialogLifecycle {
+    pub dialog_box: Entity,
+    pub writing_name: String,
+    pub stage: LifecycleStage,
+}
+
+/// The stages reported by [`DialogLifecycle`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum LifecycleStage {
+    /// The box was spawned and is about to pop up.
+    Opened,
+    /// The popup animation finished.
+    PoppedUp,
+    /// Characters started to be typed.
+    StartedTyping,
+    /// A page was typed to the end and is going to be fed.
+    PageFinished,
+    /// The box waits for the player or for a timer.
+    WaitingForInput,
+    /// The finished page is being fed.
+    Feeding,
+    /// The box opened `choice_box` with buttons labeled `labels`.
+    ChoiceOpened {
+        choice_box: Entity,
+        labels: Vec<String>,
+    },
+    /// The choice labeled `label` was chosen. `index` is its position in the script,
+    /// counting hidden choices. Turning the page of a choice box is not a choice.
+    ChoiceMade { index: usize, label: String },
+    /// The box started to close.
+    Closing,
+    /// The box was closed.
+    Closed,
 }
@@ -16,6 +16,14 @@ pub(in crate::writing) struct WaitInputGo {
     pub waiter_name: String,
 }
 
+// DialogCommands::advanceから押されたことにします。
+#[derive(Component)]
+pub(in crate::writing) struct PushedByCommand;
+
+// enable_ifを満たさない選択肢です。選ぶことも押すこともできません。
+#[derive(Component)]
+pub(in crate::writing) struct DisabledChoice;
+
 #[derive(Component)]
 pub(in crate::writing) struct Selective {
     pub key_vector: SelectVector,
@@ -23,13 +31,22 @@ pub(in crate::writing) struct Selective {
 }
 
 // ToDo: 長押しで連続スキップできるようにしときたい
-#[allow(clippy::nonminimal_bool)]
+#[allow(clippy::nonminimal_bool, clippy::type_complexity)]
 pub(in crate::writing) fn go_selected(
     mut commands: Commands,
-    target_query: Query<(Entity, &WaitInputGo, &TextArea, &ChildOf), Without<Pending>>,
+    target_query: Query<
+        (
+            Entity,
+            &WaitInputGo,
+            &TextArea,
+            &ChildOf,
+            Has<PushedByCommand>,
+        ),
+        (Without<Pending>, Without<DisabledChoice>),
+    >,
     writing_query: Query<&DialogBox>,
     selected_query: Query<Entity, (With<Selected>, Without<Pending>)>,
-    selective_query: Query<Entity, (With<Selective>, Without<Pending>)>,
+    selective_query: Query<(Entity, &ChildOf), (With<Selective>, Without<Pending>)>,
     pending_query: Query<(Entity, &Pending)>,
     window_query: Query<&Window, With<PrimaryWindow>>,
     camera_query: Query<(&Camera, &GlobalTransform), With<DialogBoxCamera>>,
@@ -52,13 +69,13 @@ pub(in crate::writing) fn go_selected(
                 .map(|y| (x, y))
         })
         .and_then(|(c, p)| c.0.viewport_to_world_2d(c.1, p).ok());
-    for (target_entity, wig, ta, ta_parent) in &target_query {
+    for (target_entity, wig, ta, ta_parent, is_pushed) in &target_query {
         let mut touched_position_list = touches
             .iter_just_pressed()
             .filter_map(|t| camera_query.single().ok().map(|c| (c, t)))
             .filter_map(|(c, t)| c.0.viewport_to_world_2d(c.1, t.position()).ok());
-        let is_selected = selected_query.single().is_ok_and(|e| e == target_entity);
-        let is_pointed = pointed_opt.is_some_and(|x| (wig.area.contains(x)));
+        let is_selected = selected_query.contains(target_entity);
+        let is_pointed = pointed_opt.is_some_and(|x| wig.area.contains(x));
         let gamepad = gamepads.iter().next();
         if (keys.any_just_pressed([KeyCode::Space, KeyCode::Enter, KeyCode::NumpadEnter])
             && is_selected)
@@ -68,39 +85,58 @@ pub(in crate::writing) fn go_selected(
                 && is_selected)
             || (mouse_buttons.just_pressed(MouseButton::Left) && is_pointed)
             || touched_position_list.any(|t| wig.area.contains(t))
+            || is_pushed
         {
+            let db_name = writing_query
+                .get(ta_parent.parent())
+                .map(|x| x.name.clone())
+                .unwrap_or_default();
             if let Ok(ref_value) = read_ron(&type_registry, wig.ron.clone()) {
-                bds_event.write(BdsEvent { value: ref_value });
+                bds_event.write(BdsEvent {
+                    value: ref_value,
+                    dialog_box: Some(ta_parent.parent()),
+                    source: BdsSource {
+                        dialog_box_name: db_name.clone(),
+                        text_area: Some(target_entity),
+                        text_area_name: ta.name.clone(),
+                        location: None,
+                    },
+                });
             }
-            let db_name_opt = writing_query
-                .get(ta_parent.parent())
-                .map(|x| x.name.clone());
             go_event.write(ButtonIsPushed {
-                writing_name: db_name_opt.unwrap_or_default(),
+                writing_name: db_name,
+                dialog_box: ta_parent.parent(),
                 text_area_name: ta.name.clone(),
+                text_area: target_entity,
             });
             for (p_entity, pending) in &pending_query {
                 if pending.name == wig.waiter_name {
                     commands.entity(p_entity).remove::<Pending>();
                 }
             }
-            for s_entity in &selective_query {
+            for (s_entity, s_parent) in &selective_query {
+                if s_parent.parent() != ta_parent.parent() {
+                    continue;
+                }
                 let pending = Pending {
                     name: "Went".to_string(),
                 };
                 commands.entity(s_entity).insert(pending);
             }
-            commands.entity(target_entity).remove::<WaitInputGo>();
+            commands
+                .entity(target_entity)
+                .remove::<(WaitInputGo, PushedByCommand)>();
         }
     }
 }
 
 // 流石に分割した方がいい気もする
+#[allow(clippy::type_complexity)]
 pub(in crate::writing) fn shift_selected(
     mut commands: Commands,
     selective_query: Query<
         (Entity, &Selective, &TextArea, &WaitInputGo, &ChildOf),
-        Without<Pending>,
+        (Without<Pending>, Without<DisabledChoice>),
     >,
     selected_query: Query<Entity, (With<Selected>, Without<Pending>)>,
     writing_query: Query<&DialogBox>,
@@ -123,144 +159,35 @@ pub(in crate::writing) fn shift_selected(
         })
         .and_then(|(c, p)| c.0.viewport_to_world_2d(c.1, p).ok());
     for (target_entity, _, _, wig, _) in &selective_query {
-        if pointed_opt.is_some_and(|x| (wig.area.contains(x))) {
+        if pointed_opt.is_some_and(|x| wig.area.contains(x)) {
             next_select_opt = Some(target_entity);
         }
     }
-    let selected_res = selected_query.single();
-    let mut vertical_targets = selective_query
+    let selected_res = selective_query
         .iter()
-        .filter(|x| x.1.key_vector == SelectVector::Vertical)
-        .collect::<Vec<_>>();
-    let mut horizen_targets = selective_query
+        .map(|x| x.0)
+        .find(|e| selected_query.contains(*e))
+        .ok_or(());
+    let mut targets = selective_query
         .iter()
-        .filter(|x| x.1.key_vector == SelectVector::Horizon)
+        .map(|(entity, selective, _, wig, _)| SelectTarget {
+            entity,
+            vector: selective.key_vector,
+            number: selective.number,
+            center: wig.area.center(),
+        })
         .collect::<Vec<_>>();
-    vertical_targets.sort_by_key(|x| x.1.number);
-    horizen_targets.sort_by_key(|x| x.1.number);
+    targets.sort_by_key(|x| x.number);
     let gamepad = gamepads.iter().next();
-    if (keys.any_just_pressed([KeyCode::ArrowUp]))
-        || gamepad.is_some_and(|x| x.just_pressed(GamepadButton::DPadUp))
-    {
-        if let Ok(selected_entity) = selected_res {
-            if let Some((
-                _,
-                Selective {
-                    number: selected_num,
-                    key_vector: SelectVector::Vertical,
-                },
-                ..,
-            )) = vertical_targets.iter().find(|x| x.0 == selected_entity)
-            {
-                let max_size = vertical_targets.iter().len() - 1;
-                let next_num = if *selected_num == 0 {
-                    max_size
-                } else {
-                    selected_num - 1
-                };
-                let next_opt = vertical_targets.iter().find(|x| x.1.number == next_num);
-                next_select_opt = next_opt.map(|x| x.0);
-            }
-        } else {
-            next_select_opt = vertical_targets
-                .iter()
-                .map(|x| x.0)
-                .collect::<Vec<_>>()
-                .last()
-                .copied();
-        }
-    }
-    if (keys.any_just_pressed([KeyCode::ArrowDown]))
-        || gamepad.is_some_and(|x| x.just_pressed(GamepadButton::DPadDown))
-    {
-        if let Ok(selected_entity) = selected_res {
-            if let Some((
-                _,
-                Selective {
-                    number: selected_num,
-                    key_vector: SelectVector::Vertical,
-                },
-                ..,
-            )) = vertical_targets.iter().find(|x| x.0 == selected_entity)
-            {
-                let max_size = vertical_targets.iter().len() - 1;
-                let next_num = if *selected_num >= max_size {
-                    0
-                } else {
-                    selected_num + 1
-                };
-                let next_opt = vertical_targets.iter().find(|x| x.1.number == next_num);
-                next_select_opt = next_opt.map(|x| x.0);
-            }
-        } else {
-            next_select_opt = vertical_targets
-                .iter()
-                .map(|x| x.0)
-                .collect::<Vec<_>>()
-                .first()
-                .copied();
-        }
-    }
-    if (keys.any_just_pressed([KeyCode::ArrowLeft]))
-        || gamepad.is_some_and(|x| x.just_pressed(GamepadButton::DPadLeft))
-    {
-        if let Ok(selected_entity) = selected_res {
-            if let Some((
-                _,
-                Selective {
-                    number: selected_num,
-                    key_vector: SelectVector::Horizon,
-                },
-                ..,
-            )) = horizen_targets.iter().find(|x| x.0 == selected_entity)
-            {
-                let max_size = horizen_targets.iter().len() - 1;
-                let next_num = if *selected_num == 0 {
-                    max_size
-                } else {
-                    selected_num - 1
-                };
-                let next_opt = horizen_targets.iter().find(|x| x.1.number == next_num);
-                next_select_opt = next_opt.map(|x| x.0);
-            }
-        } else {
-            next_select_opt = horizen_targets
-                .iter()
-                .map(|x| x.0)
-                .collect::<Vec<_>>()
-                .last()
-                .copied();
-        }
-    }
-    if (keys.any_just_pressed([KeyCode::ArrowRight]))
-        || gamepad.is_some_and(|x| x.just_pressed(GamepadButton::DPadRight))
-    {
-        if let Ok(selected_entity) = selected_res {
-            if let Some((
-                _,
-                Selective {
-                    number: selected_num,
-                    key_vector: SelectVector::Horizon,
-                },
-                ..,
-            )) = horizen_targets.iter().find(|x| x.0 == selected_entity)
-            {
-                let max_size = horizen_targets.iter().len() - 1;
-                let next_num = if *selected_num >= max_size {
-                    0
-                } else {
-                    selected_num + 1
-                };
-                let next_opt = horizen_targets.iter().find(|x| x.1.number == next_num);
-                next_select_opt = next_opt.map(|x| x.0);
-            }
-        } else {
-            next_select_opt = horizen_targets
-                .iter()
-                .map(|x| x.0)
-                .collect::<Vec<_>>()
-                .first()
-                .copied();
+    let directions = [
+        (KeyCode::ArrowUp, GamepadButton::DPadUp, IVec2::Y),
+        (KeyCode::ArrowDown, GamepadButton::DPadDown, IVec2::NEG_Y),
+        (KeyCode::ArrowLeft, GamepadButton::DPadLeft, IVec2::NEG_X),
+        (KeyCode::ArrowRight, GamepadButton::DPadRight, IVec2::X),
+    ];
+    for (key, button, direction) in directions {
+        if keys.just_pressed(key) || gamepad.is_some_and(|x| x.just_pressed(button)) {
+            next_select_opt = shift_to(&targets, selected_res, direction).or(next_select_opt);
         }
     }
     if let Some(next_entity) = next_select_opt {
@@ -272,7 +199,9 @@ pub(in crate::writing) fn shift_selected(
             let db_name_opt = writing_query.get(parent.parent()).map(|x| x.name.clone());
             let event = ButtonIsSelected {
                 writing_name: db_name_opt.unwrap_or_default(),
+                dialog_box: parent.parent(),
                 text_area_name: ta.name.clone(),
+                text_area: next_entity,
                 select_vector: selective.key_vector,
                 select_number: selective.number,
             };
@@ -280,3 +209,222 @@ pub(in crate::writing) fn shift_selected(
         }
     }
 }
+
+struct SelectTarget {
+    entity: Entity,
+    vector: SelectVector,
+    number: usize,
+    center: Vec2,
+}
+
+fn moves_along(vector: SelectVector, direction: IVec2) -> bool {
+    match vector {
+        SelectVector::Vertical => direction.y != 0,
+        SelectVector::Horizon => direction.x != 0,
+        SelectVector::Grid { .. } | SelectVector::Spatial => true,
+    }
+}
+
+// 選択中のボタンのSelectVectorに従って、directionの向きにある次のボタンを探します。
+// 何も選ばれていなければ、下と右は最初のボタンを、上と左は最後のボタンを選びます。
+fn shift_to(
+    targets: &[SelectTarget],
+    selected_res: Result<Entity, ()>,
+    direction: IVec2,
+) -> Option<Entity> {
+    let forward = direction.x > 0 || direction.y < 0;
+    let Ok(selected_entity) = selected_res else {
+        let movables = targets
+            .iter()
+            .filter(|x| moves_along(x.vector, direction))
+            .map(|x| x.entity)
+            .collect::<Vec<_>>();
+        return shift_in(&movables, Err(()), forward);
+    };
+    let selected = targets.iter().find(|x| x.entity == selected_entity)?;
+    if !moves_along(selected.vector, direction) {
+        return None;
+    }
+    let same_kind = targets
+        .iter()
+        .filter(|x| x.vector == selected.vector)
+        .collect::<Vec<_>>();
+    match selected.vector {
+        SelectVector::Vertical | SelectVector::Horizon => {
+            let line = same_kind.iter().map(|x| x.entity).collect::<Vec<_>>();
+            shift_in(&line, selected_res, forward)
+        }
+        SelectVector::Grid { columns } => shift_in_grid(&same_kind, selected, columns, direction),
+        SelectVector::Spatial => nearest_in_direction(&same_kind, selected, direction),
+    }
+}
+
+// numberを左上から行ごとに並べた位置として扱い、端まで来たら反対側に回り込みます。
+// 無効な選択肢で空いたマスは飛ばします。
+fn shift_in_grid(
+    targets: &[&SelectTarget],
+    selected: &SelectTarget,
+    columns: usize,
+    direction: IVec2,
+) -> Option<Entity> {
+    let columns = columns.max(1);
+    let rows = targets.iter().map(|x| x.number / columns).max()? + 1;
+    let (mut row, mut column) = (selected.number / columns, selected.number % columns);
+    let steps = if direction.x != 0 { columns } else { rows };
+    for _ in 1..steps {
+        if direction.x != 0 {
+            column = (column as i32 + direction.x).rem_euclid(columns as i32) as usize;
+        } else {
+            // 画面の上がyの正なので、上を押したら行を戻します。
+            row = (row as i32 - direction.y).rem_euclid(rows as i32) as usize;
+        }
+        let number = row * columns + column;
+        if let Some(found) = targets.iter().find(|x| x.number == number) {
+            return Some(found.entity);
+        }
+    }
+    None
+}
+
+// 押した向きに中心があるボタンのうち、向きに沿った距離と横ずれの2倍の和が最も小さいものを選びます。
+fn nearest_in_direction(
+    targets: &[&SelectTarget],
+    selected: &SelectTarget,
+    direction: IVec2,
+) -> Option<Entity> {
+    let dir = direction.as_vec2();
+    targets
+        .iter()
+        .filter(|x| x.entity != selected.entity)
+        .filter_map(|x| {
+            let delta = x.center - selected.center;
+            let along = delta.dot(dir);
+            (along > 0.0).then(|| (x.entity, along + delta.perp_dot(dir).abs() * 2.0))
+        })
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|x| x.0)
+}
+
+// 無効な選択肢は除かれているので、numberではなく並び順で隣を探します。
+fn shift_in(targets: &[Entity], selected_res: Result<Entity, ()>, forward: bool) -> Option<Entity> {
+    let Ok(selected_entity) = selected_res else {
+        return if forward {
+            targets.first().copied()
+        } else {
+            targets.last().copied()
+        };
+    };
+    let position = targets.iter().position(|e| *e == selected_entity)?;
+    let next_position = if forward {
+        (position + 1) % targets.len()
+    } else {
+        (position + targets.len() - 1) % targets.len()
+    };
+    targets.get(next_position).copied()
+}
+
+#[cfg(test)]
+mod select_tests {
+    use super::*;
+
+    fn entity(number: usize) -> Entity {
+        Entity::from_raw(number as u32 + 1)
+    }
+
+    // numberの番号のボタンを3列の格子に並べます。
+    fn grid(numbers: &[usize]) -> Vec<SelectTarget> {
+        numbers
+            .iter()
+            .map(|&number| SelectTarget {
+                entity: entity(number),
+                vector: SelectVector::Grid { columns: 3 },
+                number,
+                center: Vec2::ZERO,
+            })
+            .collect()
+    }
+
+    fn spatial(centers: &[Vec2]) -> Vec<SelectTarget> {
+        centers
+            .iter()
+            .enumerate()
+            .map(|(number, &center)| SelectTarget {
+                entity: entity(number),
+                vector: SelectVector::Spatial,
+                number,
+                center,
+            })
+            .collect()
+    }
+
+    fn grid_step(targets: &[SelectTarget], from: usize, direction: IVec2) -> Option<Entity> {
+        let refs = targets.iter().collect::<Vec<_>>();
+        let selected = targets.iter().find(|x| x.number == from).unwrap();
+        shift_in_grid(&refs, selected, 3, direction)
+    }
+
+    fn spatial_step(targets: &[SelectTarget], from: usize, direction: IVec2) -> Option<Entity> {
+        let refs = targets.iter().collect::<Vec<_>>();
+        nearest_in_direction(&refs, &targets[from], direction)
+    }
+
+    #[test]
+    fn test_grid_moves_and_wraps_around() {
+        let targets = grid(&[0, 1, 2, 3, 4, 5]);
+        assert_eq!(grid_step(&targets, 0, IVec2::X), Some(entity(1)));
+        assert_eq!(grid_step(&targets, 2, IVec2::X), Some(entity(0)));
+        assert_eq!(grid_step(&targets, 0, -IVec2::X), Some(entity(2)));
+        assert_eq!(grid_step(&targets, 1, -IVec2::Y), Some(entity(4)));
+        assert_eq!(grid_step(&targets, 4, -IVec2::Y), Some(entity(1)));
+        assert_eq!(grid_step(&targets, 1, IVec2::Y), Some(entity(4)));
+    }
+
+    #[test]
+    fn test_grid_skips_ragged_last_row() {
+        // 0 1 2
+        // 3 4
+        let targets = grid(&[0, 1, 2, 3, 4]);
+        assert_eq!(grid_step(&targets, 4, IVec2::X), Some(entity(3)));
+        assert_eq!(grid_step(&targets, 3, -IVec2::X), Some(entity(4)));
+        assert_eq!(grid_step(&targets, 0, IVec2::Y), Some(entity(3)));
+        // 下の行にマスがなければ動きません。
+        assert_eq!(grid_step(&targets, 2, -IVec2::Y), None);
+        assert_eq!(grid_step(&targets, 2, IVec2::Y), None);
+    }
+
+    #[test]
+    fn test_grid_skips_disabled_cells() {
+        // 0 _ 2
+        // _ 4 5
+        let targets = grid(&[0, 2, 4, 5]);
+        assert_eq!(grid_step(&targets, 0, IVec2::X), Some(entity(2)));
+        assert_eq!(grid_step(&targets, 5, IVec2::X), Some(entity(4)));
+        assert_eq!(grid_step(&targets, 0, -IVec2::Y), None);
+        assert_eq!(grid_step(&targets, 5, IVec2::Y), Some(entity(2)));
+    }
+
+    #[test]
+    fn test_nearest_prefers_aligned_targets() {
+        let targets = spatial(&[
+            Vec2::ZERO,
+            Vec2::new(100.0, 0.0),
+            Vec2::new(60.0, 30.0),
+            Vec2::new(-50.0, 0.0),
+        ]);
+        // 60 + 30 * 2より100のほうが近いものとします。
+        assert_eq!(spatial_step(&targets, 0, IVec2::X), Some(entity(1)));
+        assert_eq!(spatial_step(&targets, 0, -IVec2::X), Some(entity(3)));
+        assert_eq!(spatial_step(&targets, 0, IVec2::Y), Some(entity(2)));
+        // 押した向きに何もなければ回り込みません。
+        assert_eq!(spatial_step(&targets, 0, -IVec2::Y), None);
+        assert_eq!(spatial_step(&targets, 1, IVec2::X), None);
+    }
+
+    #[test]
+    fn test_nearest_ties_keep_target_order() {
+        let targets = spatial(&[Vec2::ZERO, Vec2::new(50.0, 10.0), Vec2::new(50.0, -10.0)]);
+        assert_eq!(spatial_step(&targets, 0, IVec2::X), Some(entity(1)));
+        let reversed = spatial(&[Vec2::ZERO, Vec2::new(50.0, -10.0), Vec2::new(50.0, 10.0)]);
+        assert_eq!(spatial_step(&reversed, 0, IVec2::X), Some(entity(1)));
+    }
+}
@@ -1,26 +1,43 @@
 use crate::read_script::*;
 use bevy::prelude::*;
 
+mod control;
 pub mod events;
 pub(crate) mod input;
 pub mod settings;
 mod setup;
+#[cfg(test)]
+mod test_app;
 mod text_controller;
+mod text_input;
+mod variables;
 pub(crate) mod window_controller;
 
+pub use control::*;
 pub use events::*;
 use input::*;
 pub use settings::configs::*;
 pub use settings::params::*;
 use setup::*;
+use text_controller::batched::*;
+pub use text_controller::batched::{BatchedGlyph, BatchedLine};
 use text_controller::feed_animation::*;
+use text_controller::glyph_cache::*;
+use text_controller::measure::*;
+pub use text_controller::measure::{
+    layout_orders, layout_script, LaidOutLine, LaidOutPage, PageBreak, TextLayout,
+};
 use text_controller::typing_animations::*;
 use text_controller::*;
+pub use text_input::*;
+pub use variables::*;
 use window_controller::choice::*;
+use window_controller::lifecycle::*;
 use window_controller::popup::*;
 use window_controller::sinkdown::*;
 use window_controller::waiting::*;
 use window_controller::*;
+pub use window_controller::{DialogBox, DialogBoxPhase, TextArea};
 
 pub struct DialogBoxPlugin {
     pub layer_num: u8,
@@ -61,10 +78,13 @@ impl Plugin for DialogBoxPlugin {
             .register_type::<ChangeCurrentTextAreaInCurrentBox>()
             .register_type::<ChangeCurrentDialogBox>()
             .register_type::<LoadBds>()
+            .register_type::<CallBds>()
+            .register_type::<ReturnBds>()
             .register_type::<(String, String)>()
             .register_type::<Vec<(String, String)>>()
             .register_type::<SetupChoice>()
             .register_type::<ChoosenEvent>()
+            .register_type::<TurnChoicePage>()
             .register_type::<SinkDownWindow>()
             .register_type::<InputForFeeding>()
             .register_type::<InputForSkipping>()
@@ -74,6 +94,20 @@ impl Plugin for DialogBoxPlugin {
             .register_type::<BreakWait>()
             .register_type::<SimpleStringSignal>()
             .register_type::<ForceFeedingCurrentBox>()
+            .register_type::<ChoiceCondition>()
+            .register_type::<Vec<ChoiceCondition>>()
+            .register_dialog_command::<LoadBds>("jump")
+            .register_dialog_command::<CallBds>("call")
+            .register_dialog_command::<ReturnBds>("return")
+            .register_dialog_command::<SimpleWait>("wait")
+            .register_dialog_command::<ForceFeedingCurrentBox>("feed")
+            .register_dialog_command::<SinkDownWindow>("close")
+            .register_dialog_command::<SimpleStringSignal>("signal")
+            .register_dialog_command::<ChangeFontSize>("font_size")
+            .register_dialog_command::<SetVariable>("set")
+            .register_dialog_command::<InputText>("input")
+            .init_resource::<ScriptVariables>()
+            .init_resource::<GlyphCache>()
             .add_event::<OpenDialog>()
             .add_event::<FeedWaitingEvent>()
             .add_event::<StartFeedingEvent>()
@@ -81,14 +115,26 @@ impl Plugin for DialogBoxPlugin {
             .add_event::<ButtonIsPushed>()
             .add_event::<GoSinking>()
             .add_event::<FinisClosingBox>()
+            .add_event::<DialogLifecycle>()
+            .add_event::<TextInputConfirmed>()
+            // WindowPluginがなくても動くように自前でも登録します。
+            .add_event::<bevy::window::Ime>()
             .add_event::<BdsSignal>()
             .add_event::<BdsEvent>()
+            .add_event::<DialogScriptError>()
             .configure_sets(
                 Update,
                 (PhaseSet::Setting, PhaseSet::Progress, PhaseSet::Fire).chain(),
             )
             .add_systems(Startup, setup_camera)
             .add_systems(Update, script_on_load.in_set(PhaseSet::Setting))
+            .add_systems(Update, invalidate_glyph_cache.in_set(PhaseSet::Setting))
+            .add_systems(
+                Update,
+                fit_area_to_page
+                    .in_set(PhaseSet::Setting)
+                    .after(script_on_load),
+            )
             .add_systems(Update, trigger_type_animation.in_set(PhaseSet::Setting))
             .add_systems(Update, setup_window_sink.in_set(PhaseSet::Setting))
             .add_systems(Update, waiting_icon_setting.in_set(PhaseSet::Setting))
@@ -101,6 +147,7 @@ impl Plugin for DialogBoxPlugin {
             )
             .add_systems(Update, change_current_writing.in_set(PhaseSet::Setting))
             .add_systems(Update, change_font_size.in_set(PhaseSet::Setting))
+            .add_systems(Update, set_variable.in_set(PhaseSet::Setting))
             .add_systems(Update, setup_choice.in_set(PhaseSet::Setting))
             .add_systems(Update, despawn_writing.in_set(PhaseSet::Setting))
             .add_systems(Update, remove_pending.in_set(PhaseSet::Setting))
@@ -109,26 +156,45 @@ impl Plugin for DialogBoxPlugin {
                 reinstatement_external_entities.in_set(PhaseSet::Setting),
             )
             .add_systems(Update, add_new_text.in_set(PhaseSet::Fire))
+            .add_systems(Update, send_lifecycle_events.after(PhaseSet::Fire))
             .add_systems(Update, settle_wating_icon.in_set(PhaseSet::Progress))
+            .add_systems(Update, count_down_choice.in_set(PhaseSet::Progress))
+            .add_systems(Update, turn_choice_page.in_set(PhaseSet::Progress))
             .add_systems(Update, settle_lines.in_set(PhaseSet::Progress))
             .add_systems(Update, text_wipe.in_set(PhaseSet::Progress))
+            .add_systems(Update, reveal_batched_glyphs.in_set(PhaseSet::Progress))
+            .add_systems(
+                Update,
+                draw_batched_lines
+                    .in_set(PhaseSet::Progress)
+                    .after(reveal_batched_glyphs),
+            )
             .add_systems(Update, hide_waiting_icon.in_set(PhaseSet::Progress))
             .add_systems(Update, scaling_up.in_set(PhaseSet::Progress))
             .add_systems(Update, scaling_down.in_set(PhaseSet::Progress))
             .add_systems(Update, scroll_lines.in_set(PhaseSet::Progress))
             .add_systems(Update, simple_wait.in_set(PhaseSet::Progress))
+            .add_systems(Update, open_text_entry.in_set(PhaseSet::Progress))
+            .add_systems(Update, edit_text_entry.in_set(PhaseSet::Progress))
             .add_systems(Update, skip_typing_or_next.in_set(PhaseSet::Progress))
             .add_systems(Update, setup_feed_starter.in_set(PhaseSet::Progress))
             .add_systems(Update, open_window.in_set(PhaseSet::Fire))
             .add_systems(Update, open_choice_box.in_set(PhaseSet::Fire))
             .add_systems(Update, load_bds.in_set(PhaseSet::Fire))
+            .add_systems(Update, call_bds.in_set(PhaseSet::Fire))
             .add_systems(Update, window_popper.in_set(PhaseSet::Fire))
             .add_systems(Update, start_window_sink.in_set(PhaseSet::Fire))
             .add_systems(Update, trigger_feeding_by_time.in_set(PhaseSet::Fire))
             .add_systems(Update, close_choice_phase.in_set(PhaseSet::Fire))
             .add_systems(Update, trigger_feeding_by_event.in_set(PhaseSet::Fire))
-            .add_systems(Update, go_selected.in_set(PhaseSet::Fire))
-            .add_systems(Update, shift_selected.in_set(PhaseSet::Fire))
+            .add_systems(
+                Update,
+                go_selected.in_set(PhaseSet::Fire).run_if(no_text_entry),
+            )
+            .add_systems(
+                Update,
+                shift_selected.in_set(PhaseSet::Fire).run_if(no_text_entry),
+            )
             .add_systems(Update, force_feeding_current_box.in_set(PhaseSet::Fire))
             .add_systems(Update, send_bds_signal.in_set(PhaseSet::Fire))
             .add_systems(
@@ -7,6 +7,8 @@ use std::collections::HashMap;
 #[derive(Component)]
 pub struct TypeTextConfig {
     pub text_fonts: Vec<TextFont>,
+    pub font_scripts: Vec<Vec<CharScript>>,
+    pub keep_punctuation_in_run: bool,
     pub kerning_by_regulars: HashMap<String, f32>,
     pub size_by_regulars: HashMap<String, f32>,
     pub text_color: TextColor,
@@ -18,6 +20,9 @@ pub struct TypeTextConfig {
     pub vertical_alignment: AlignVertical,
     pub monospace: bool,
     pub pos_z: f32,
+    pub rendering: GlyphRendering,
+    pub area_origin: Vec2,
+    pub area_size: Vec2,
 }
 
 #[derive(Clone)]
@@ -27,6 +32,9 @@ pub struct CharConfig {
     pub size_by_regulars: HashMap<String, f32>,
     pub text_base_size: f32,
     pub font_color: TextColor,
+    /// Writes characters without a [`CharScript`], such as spaces and quotes, with the font
+    /// of the character before them if it has the glyph.
+    pub keep_punctuation_in_run: bool,
 }
 
 impl Default for CharConfig {
@@ -37,6 +45,7 @@ impl Default for CharConfig {
             size_by_regulars: HashMap::default(),
             text_base_size: 27.0,
             font_color: CssColor::ANTIQUE_WHITE.into(),
+            keep_punctuation_in_run: false,
         }
     }
 }
@@ -54,6 +63,8 @@ pub struct TextAreaConfig {
     pub typing_timing: TypingTiming,
     pub writing: WritingStyle,
     pub text_pos_z: f32,
+    pub fitting: AreaFitting,
+    pub rendering: GlyphRendering,
 }
 
 impl Default for TextAreaConfig {
@@ -70,6 +81,8 @@ impl Default for TextAreaConfig {
             typing_timing: TypingTiming::ByChar { sec: 0.07 },
             writing: WritingStyle::Wipe { sec: 0.07 },
             text_pos_z: 1.0,
+            fitting: AreaFitting::Fixed,
+            rendering: GlyphRendering::default(),
         }
     }
 }
@@ -77,6 +90,8 @@ impl Default for TextAreaConfig {
 #[derive(Component, Clone)]
 pub struct ChoiceBoxConfig {
     pub choice_box_name: String,
+    // Someなら名前で探さずにこのEntityを選択肢の箱にします。
+    pub choice_box: Option<Entity>,
     pub button_text_areas: Vec<TextAreaConfig>,
     pub popup: PopupType,
     pub sinkdown: SinkDownType,
@@ -84,6 +99,33 @@ pub struct ChoiceBoxConfig {
     pub select_vector: SelectVector,
     pub background_scaling_per_button: Vec2,
     pub background_scaling_anchor: Anchor,
+    // enable_ifを満たさない選択肢の文字色です。
+    pub disabled_color: TextColor,
+    // Noneなら選ばれるまで待ち続けます。
+    pub time_limit: Option<ChoiceTimeLimit>,
+    pub overflow: ChoiceOverflow,
+}
+
+// 残り時間のバーは選択肢の箱の子としてbar_offsetに置かれ、左端を基準に縮みます。
+#[derive(Clone, Debug)]
+pub struct ChoiceTimeLimit {
+    pub sec: f32,
+    pub fallback: TimeoutChoice,
+    pub bar_size: Vec2,
+    pub bar_offset: Vec2,
+    pub bar_color: Color,
+}
+
+impl Default for ChoiceTimeLimit {
+    fn default() -> Self {
+        ChoiceTimeLimit {
+            sec: 10.0,
+            fallback: TimeoutChoice::Selected,
+            bar_size: Vec2::new(400., 8.),
+            bar_offset: Vec2::new(-200., 0.),
+            bar_color: CssColor::ANTIQUE_WHITE.into(),
+        }
+    }
 }
 
 impl Default for ChoiceBoxConfig {
@@ -114,12 +156,16 @@ impl Default for ChoiceBoxConfig {
                 },
             ],
             choice_box_name: "Choice Box".to_string(),
+            choice_box: None,
             popup: PopupType::Scale { sec: 0.8 },
             sinkdown: SinkDownType::Scale { sec: 0.8 },
             wait_to_sink: 0.0,
             select_vector: SelectVector::Vertical,
             background_scaling_per_button: Vec2::new(0., 100.),
             background_scaling_anchor: Anchor::TopLeft,
+            disabled_color: Color::srgb(0.5, 0.5, 0.5).into(),
+            time_limit: None,
+            overflow: ChoiceOverflow::default(),
         }
     }
 }
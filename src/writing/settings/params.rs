@@ -5,13 +5,25 @@ use bevy::prelude::*;
 #[derive(Component, Debug, Default)]
 pub struct WaitingIcon {
     pub target_box_name: String,
+    pub target_box: Option<Entity>,
     pub wait_for: Vec<WaitTarget>,
 }
 
+impl WaitingIcon {
+    // target_boxがあればそちらを優先し、なければ名前で照合します。
+    pub fn is_for(&self, db_entity: Entity, db_name: &str) -> bool {
+        self.target_box
+            .map_or(self.target_box_name == db_name, |e| e == db_entity)
+    }
+}
+
 #[derive(Clone)]
 pub struct FontSettings {
     pub path: String,
     pub size_coefficient: f32,
+    /// The scripts this font is used for first. Characters of a script are written with
+    /// the first font that lists it, in the order of `font_settings`. Empty means none.
+    pub scripts: Vec<CharScript>,
 }
 
 impl Default for FontSettings {
@@ -19,10 +31,56 @@ impl Default for FontSettings {
         Self {
             path: "fonts/FiraMono-Regular.ttf".to_string(),
             size_coefficient: 1.0,
+            scripts: Vec::new(),
         }
     }
 }
 
+/// A group of characters that can be given its own font with [`FontSettings::scripts`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CharScript {
+    Latin,
+    Greek,
+    Cyrillic,
+    Hebrew,
+    Arabic,
+    /// Kanji, including `々`.
+    Han,
+    /// Hiragana, katakana and half-width katakana.
+    Kana,
+    Hangul,
+    /// Pictographs and dingbats.
+    Emoji,
+}
+
+impl CharScript {
+    /// Returns the script of `c`, or `None` for characters shared by every script such as
+    /// spaces, digits and punctuation.
+    pub fn of(c: char) -> Option<Self> {
+        let script = match c as u32 {
+            0x41..=0x5A | 0x61..=0x7A | 0xC0..=0xD6 | 0xD8..=0xF6 | 0xF8..=0x24F => {
+                CharScript::Latin
+            }
+            0x1E00..=0x1EFF | 0xFF21..=0xFF3A | 0xFF41..=0xFF5A => CharScript::Latin,
+            0x370..=0x3FF | 0x1F00..=0x1FFF => CharScript::Greek,
+            0x400..=0x52F => CharScript::Cyrillic,
+            0x590..=0x5FF => CharScript::Hebrew,
+            0x600..=0x6FF | 0x750..=0x77F => CharScript::Arabic,
+            0x3005 | 0x3400..=0x4DBF | 0x4E00..=0x9FFF | 0xF900..=0xFAFF | 0x20000..=0x2FFFF => {
+                CharScript::Han
+            }
+            0x3041..=0x309F | 0x30A0..=0x30FA | 0x30FC..=0x30FF | 0x31F0..=0x31FF => {
+                CharScript::Kana
+            }
+            0xFF66..=0xFF9F => CharScript::Kana,
+            0x1100..=0x11FF | 0x3130..=0x318F | 0xAC00..=0xD7AF => CharScript::Hangul,
+            0x2600..=0x27BF | 0x1F000..=0x1FAFF => CharScript::Emoji,
+            _ => return None,
+        };
+        Some(script)
+    }
+}
+
 #[derive(Component, Debug, PartialEq)]
 pub enum WaitTarget {
     Feeding,
@@ -34,12 +92,20 @@ pub struct DialogBoxBackground {
     pub writing_name: String,
 }
 
-#[derive(Component)]
+#[derive(Component, Clone)]
 pub struct ChoiceButton {
     pub target_box_name: String,
+    pub target_box: Option<Entity>,
     pub sort_number: usize,
 }
 
+impl ChoiceButton {
+    pub fn is_for(&self, db_entity: Entity, db_name: &str) -> bool {
+        self.target_box
+            .map_or(self.target_box_name == db_name, |e| e == db_entity)
+    }
+}
+
 #[derive(Component, Debug, Clone, Copy, PartialEq, Default, Reflect)]
 pub enum SinkDownType {
     #[default]
@@ -56,6 +122,16 @@ pub enum TypingTiming {
     ByPage,
 }
 
+// PerCharは一文字ごとにText2dを作ります。
+// Batchedは行の文字を、測った位置どおりに続くまとまりごとに一つのText2dにします。
+// 文字ごとの出具合はBatchedLineに持ち、Wipeは透明度で表します。
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub enum GlyphRendering {
+    #[default]
+    PerChar,
+    Batched,
+}
+
 #[derive(Component, Default, Debug, Clone, Copy, PartialEq)]
 pub enum WritingStyle {
     Wipe {
@@ -90,10 +166,72 @@ pub enum WaitBrakerStyle {
     },
 }
 
+// FitToPageはページごとに文字列を計測し、設定したTextAreaの中心を保ったまま大きさを変えます。
+// DialogBoxのSpriteはTextAreaの大きさにbox_marginを足した大きさになります。
+#[derive(Component, Debug, Clone, Copy, PartialEq, Default)]
+pub enum AreaFitting {
+    #[default]
+    Fixed,
+    FitToPage {
+        min: Vec2,
+        max: Vec2,
+        box_margin: Vec2,
+    },
+}
+
+// 制限時間が切れたときに選ぶ選択肢です。
+// Selectedは選択中のもの、なければ最初に選べるものを選びます。
+// Indexはスクリプトに書かれた順番で、隠れているか選べないか別の頁にあれば最初に選べるものを選びます。
+// 選べるものがひとつもなければ、何も選ばずに閉じてスクリプトの続きを読みます。
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum TimeoutChoice {
+    #[default]
+    Selected,
+    Index(usize),
+}
+
+// 読もうとしたsectionがないときの扱いです。どの場合もDialogScriptErrorは送られます。
+// Fallbackは同じスクリプトのsectionを代わりに読みます。
+// Closeは書き終わったときと同じように入力を待ってから、sink_typeで箱を閉じます。
+#[derive(Component, Debug, Clone, PartialEq)]
+pub enum MissingSectionPolicy {
+    Fallback { section: String },
+    Close { sink_type: SinkDownType },
+}
+
+impl Default for MissingSectionPolicy {
+    fn default() -> Self {
+        MissingSectionPolicy::Close {
+            sink_type: SinkDownType::default(),
+        }
+    }
+}
+
+// 選択肢がbutton_text_areasより多いときの扱いです。
+// Pagingは最後の枠をnext_labelの頁送りにします。枠が一つしかなければ、枠の高さずつ下にExtendします。
+// Extendは最後のTextAreaとChoiceButtonをstepずつずらして複製します。
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChoiceOverflow {
+    Paging { next_label: String },
+    Extend { step: Vec2 },
+}
+
+impl Default for ChoiceOverflow {
+    fn default() -> Self {
+        ChoiceOverflow::Paging {
+            next_label: "Next".to_string(),
+        }
+    }
+}
+
+// Gridはnumberを左上から行ごとにcolumns個ずつ並べたものとして上下左右に動きます。
+// Spatialはボタンの位置を見て、押した向きで一番近いボタンに動きます。
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum SelectVector {
     Vertical,
     Horizon,
+    Grid { columns: usize },
+    Spatial,
 }
 
 #[derive(Clone, Copy, PartialEq)]
@@ -109,3 +247,85 @@ pub enum AlignHorizon {
     Center,
     Right,
 }
+
+#[cfg(test)]
+mod char_script_tests {
+    use super::*;
+
+    fn scripts(s: &str) -> Vec<Option<CharScript>> {
+        s.chars().map(CharScript::of).collect()
+    }
+
+    #[test]
+    fn test_latin_boundaries() {
+        use CharScript::Latin;
+        assert_eq!(
+            scripts("@AZ[`az{"),
+            [
+                None,
+                Some(Latin),
+                Some(Latin),
+                None,
+                None,
+                Some(Latin),
+                Some(Latin),
+                None
+            ]
+        );
+        assert_eq!(
+            scripts("À×Øö÷øɏ"),
+            [
+                Some(Latin),
+                None,
+                Some(Latin),
+                Some(Latin),
+                None,
+                Some(Latin),
+                Some(Latin)
+            ]
+        );
+        // 全角の英字はLatinですが、全角の数字と記号は含めません。
+        assert_eq!(
+            scripts("＠ＡＺ［ａｚ０"),
+            [
+                None,
+                Some(Latin),
+                Some(Latin),
+                None,
+                Some(Latin),
+                Some(Latin),
+                None
+            ]
+        );
+    }
+
+    #[test]
+    fn test_japanese_boundaries() {
+        use CharScript::{Han, Kana};
+        assert_eq!(scripts("ぁゟァヺ"), [Some(Kana); 4]);
+        // ーはカナに含め、・は区切りとしてどの文字にも属しません。
+        assert_eq!(scripts("ー・"), [Some(Kana), None]);
+        assert_eq!(scripts("々〆"), [Some(Han), None]);
+        assert_eq!(scripts("一龥豈"), [Some(Han); 3]);
+        assert_eq!(scripts("･ｦﾟ"), [None, Some(Kana), Some(Kana)]);
+        assert_eq!(scripts("、。「"), [None; 3]);
+    }
+
+    #[test]
+    fn test_other_scripts() {
+        assert_eq!(CharScript::of('α'), Some(CharScript::Greek));
+        assert_eq!(CharScript::of('Ж'), Some(CharScript::Cyrillic));
+        assert_eq!(CharScript::of('א'), Some(CharScript::Hebrew));
+        assert_eq!(CharScript::of('ع'), Some(CharScript::Arabic));
+        assert_eq!(CharScript::of('한'), Some(CharScript::Hangul));
+        assert_eq!(CharScript::of(' '), None);
+        assert_eq!(CharScript::of('1'), None);
+    }
+
+    #[test]
+    fn test_emoji_boundaries() {
+        use CharScript::Emoji;
+        assert_eq!(scripts("☀➿😀🫶"), [Some(Emoji); 4]);
+        assert_eq!(scripts("\u{25FF}\u{27C0}\u{1FB00}"), [None; 3]);
+    }
+}
@@ -0,0 +1,70 @@
+use bevy::{
+    asset::io::embedded::EmbeddedAssetRegistry, input::InputPlugin, prelude::*,
+    state::app::StatesPlugin, text::FontLoader,
+};
+use std::path::{Path, PathBuf};
+
+use super::*;
+
+// ウィンドウなしでDialogBoxPluginを動かします。
+pub(in crate::writing) fn test_app() -> App {
+    let mut app = App::new();
+    app.add_plugins((
+        MinimalPlugins,
+        AssetPlugin::default(),
+        InputPlugin,
+        StatesPlugin,
+    ))
+    .init_asset::<Font>()
+    .init_asset::<Image>()
+    .init_asset::<TextureAtlasLayout>()
+    .init_asset_loader::<FontLoader>()
+    .add_plugins(DialogBoxPlugin::default());
+    app
+}
+
+// アセットの読み込みを待つために少しずつ進めます。
+pub(in crate::writing) fn run(app: &mut App, frames: usize) {
+    for _ in 0..frames {
+        app.update();
+        std::thread::sleep(std::time::Duration::from_millis(2));
+    }
+}
+
+// スクリプトを読まずに、ordersをすぐに打つ箱の設定です。
+pub(in crate::writing) fn raw_dialog(orders: Vec<Order>) -> OpenDialog {
+    OpenDialog {
+        template_path: vec![],
+        raw_orders: Some(orders),
+        popup: PopupType::Scale { sec: 0.0001 },
+        wait_breaker: WaitBrakerStyle::Input {
+            is_icon_moving_to_last: false,
+            is_all_range_area: true,
+        },
+        ..default()
+    }
+}
+
+pub(in crate::writing) fn text(s: &str) -> Order {
+    Order::Text {
+        text: s.to_string(),
+    }
+}
+
+// 赤薔薇を埋め込み、その字で打つ文字欄の設定を返します。
+pub(in crate::writing) fn font_area(app: &mut App) -> TextAreaConfig {
+    let font =
+        include_bytes!("../ui_templates/rose_style/assets/fonts/赤薔薇/akabara-cinderella.ttf");
+    let registry = app.world().resource::<EmbeddedAssetRegistry>();
+    registry.insert_asset(PathBuf::new(), Path::new("test/font.ttf"), font.as_slice());
+    TextAreaConfig {
+        text_config: CharConfig {
+            font_settings: vec![FontSettings {
+                path: "embedded://test/font.ttf".to_string(),
+                ..default()
+            }],
+            ..default()
+        },
+        ..default()
+    }
+}
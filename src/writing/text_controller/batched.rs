@@ -0,0 +1,260 @@
+use bevy::{prelude::*, render::view::RenderLayers, sprite::Anchor};
+
+use super::*;
+
+/// A glyph of a [`BatchedLine`].
+#[derive(Debug, Clone)]
+pub struct BatchedGlyph {
+    pub character: char,
+    /// The x position in the line the glyph was measured at.
+    pub x: f32,
+    /// The measured advance of the glyph, without kerning.
+    pub advance: f32,
+    pub font: TextFont,
+    /// The color of the glyph. Its alpha is multiplied by `progress` when drawn.
+    pub color: Color,
+    /// How much the glyph is revealed, from `0.0` to `1.0`.
+    pub progress: f32,
+    delay: Timer,
+    reveal_per_sec: Option<f32>,
+}
+
+impl BatchedGlyph {
+    fn is_revealed(&self) -> bool {
+        self.progress >= 1.0
+    }
+}
+
+/// The glyphs of a line drawn as one text layout with [`GlyphRendering::Batched`].
+///
+/// Every glyph keeps its own reveal progress and color, so per-glyph effects can be
+/// written by changing `glyphs`.
+#[derive(Component, Debug)]
+pub struct BatchedLine {
+    pub glyphs: Vec<BatchedGlyph>,
+    layer: RenderLayers,
+    runs: Vec<Entity>,
+}
+
+impl BatchedLine {
+    pub(in crate::writing) fn new(layer: RenderLayers) -> Self {
+        BatchedLine {
+            glyphs: Vec::new(),
+            layer,
+            runs: Vec::new(),
+        }
+    }
+
+    /// Returns `true` if every glyph is revealed.
+    pub fn is_revealed(&self) -> bool {
+        self.glyphs.iter().all(BatchedGlyph::is_revealed)
+    }
+
+    /// Reveals every glyph at once.
+    pub fn reveal_all(&mut self) {
+        for glyph in &mut self.glyphs {
+            let rem = glyph.delay.remaining();
+            glyph.delay.tick(rem);
+            glyph.progress = 1.0;
+        }
+    }
+
+    // 最後の文字が出るまでの残り時間です。
+    pub(in crate::writing) fn remaining_secs(&self) -> f32 {
+        self.glyphs.last().map_or(0.0, |g| g.delay.remaining_secs())
+    }
+}
+
+// add_charで測った文字を行に足します。行はadd_empty_lineでBatchedLineを持って作られています。
+pub(in crate::writing) fn push_glyph(
+    commands: &mut Commands,
+    line: Entity,
+    character: char,
+    (x, advance): (f32, f32),
+    font: TextFont,
+    (config, timer): (&TypeTextConfig, &TypingTimer),
+) {
+    let glyph = BatchedGlyph {
+        character,
+        x,
+        advance,
+        font,
+        color: config.text_color.0,
+        progress: 0.0,
+        delay: timer.timer.clone(),
+        reveal_per_sec: match config.writing {
+            WritingStyle::Wipe { sec } => Some(1.0 / sec),
+            WritingStyle::Put => None,
+        },
+    };
+    commands
+        .entity(line)
+        .entry::<BatchedLine>()
+        .and_modify(move |mut l| l.glyphs.push(glyph));
+}
+
+// WipeはTransformで一文字ずつ縮められないので、まとめて描くときは透明度で出します。
+pub(in crate::writing) fn reveal_batched_glyphs(
+    mut line_query: Query<&mut BatchedLine>,
+    time: Res<Time>,
+) {
+    for mut line in &mut line_query {
+        if line.is_revealed() {
+            continue;
+        }
+        for glyph in line.glyphs.iter_mut().filter(|g| !g.is_revealed()) {
+            if !glyph.delay.tick(time.delta()).finished() {
+                continue;
+            }
+            glyph.progress = match glyph.reveal_per_sec {
+                Some(per_sec) => (glyph.progress + time.delta_secs() * per_sec).min(1.0),
+                None => 1.0,
+            };
+        }
+    }
+}
+
+// 出ている文字を、フォントと色が同じで測った位置どおりに続くものごとにまとめます。
+// カーニングや等幅で位置がずれる文字からは、その位置に新しいまとまりを置きます。
+fn batch_runs(glyphs: &[BatchedGlyph]) -> Vec<(f32, String, TextFont, Color)> {
+    let mut runs: Vec<(f32, String, TextFont, Color)> = Vec::new();
+    let mut next_x = None;
+    for glyph in glyphs.iter().take_while(|g| g.progress > 0.0) {
+        let color = glyph.color.with_alpha(glyph.color.alpha() * glyph.progress);
+        let is_adjoined = next_x.is_some_and(|x: f32| (glyph.x - x).abs() < 0.01);
+        match runs.last_mut() {
+            Some((_, s, font, c))
+                if is_adjoined
+                    && font.font == glyph.font.font
+                    && font.font_size == glyph.font.font_size
+                    && *c == color =>
+            {
+                s.push(glyph.character);
+            }
+            _ => runs.push((
+                glyph.x,
+                glyph.character.to_string(),
+                glyph.font.clone(),
+                color,
+            )),
+        }
+        next_x = Some(glyph.x + glyph.advance);
+    }
+    runs
+}
+
+pub(in crate::writing) fn draw_batched_lines(
+    mut commands: Commands,
+    mut line_query: Query<(Entity, &mut BatchedLine), Changed<BatchedLine>>,
+    mut run_query: Query<(&mut Text2d, &mut TextFont, &mut TextColor, &mut Transform)>,
+) {
+    for (line_entity, mut line) in &mut line_query {
+        let line = line.bypass_change_detection();
+        let runs = batch_runs(&line.glyphs);
+        let run_count = runs.len();
+        for (i, (x, s, font, color)) in runs.into_iter().enumerate() {
+            match line.runs.get(i) {
+                Some(run) => {
+                    if let Ok((mut text, mut t_font, mut t_color, mut tf)) = run_query.get_mut(*run)
+                    {
+                        text.0 = s;
+                        *t_font = font;
+                        t_color.0 = color;
+                        tf.translation.x = x;
+                    }
+                }
+                None => {
+                    let run = commands
+                        .spawn((
+                            Text2d::new(s),
+                            font,
+                            TextColor(color),
+                            Anchor::BottomLeft,
+                            Transform::from_xyz(x, 0.0, 0.0),
+                            line.layer.clone(),
+                            ChildOf(line_entity),
+                        ))
+                        .id();
+                    line.runs.push(run);
+                }
+            }
+        }
+        for run in line.runs.drain(run_count.min(line.runs.len())..) {
+            commands.entity(run).despawn();
+        }
+    }
+}
+
+#[cfg(test)]
+mod batched_tests {
+    use super::*;
+
+    fn glyph(character: char, x: f32, advance: f32) -> BatchedGlyph {
+        BatchedGlyph {
+            character,
+            x,
+            advance,
+            font: TextFont::default(),
+            color: Color::WHITE,
+            progress: 1.0,
+            delay: Timer::default(),
+            reveal_per_sec: None,
+        }
+    }
+
+    fn texts(glyphs: &[BatchedGlyph]) -> Vec<(f32, String)> {
+        batch_runs(glyphs)
+            .into_iter()
+            .map(|(x, s, _, _)| (x, s))
+            .collect()
+    }
+
+    #[test]
+    fn test_adjoined_glyphs_are_one_run() {
+        let glyphs = [
+            glyph('a', 0.0, 10.0),
+            glyph('b', 10.0, 8.0),
+            glyph('c', 18.0, 9.0),
+        ];
+        assert_eq!(texts(&glyphs), vec![(0.0, "abc".to_string())]);
+    }
+
+    #[test]
+    fn test_kerned_or_monospaced_glyphs_start_runs_at_measured_x() {
+        // bの後ろがカーニングで2詰められ、cは等幅で広げられています。
+        let glyphs = [
+            glyph('a', 0.0, 10.0),
+            glyph('b', 10.0, 8.0),
+            glyph('c', 16.0, 6.0),
+            glyph('d', 24.0, 6.0),
+        ];
+        assert_eq!(
+            texts(&glyphs),
+            vec![
+                (0.0, "ab".to_string()),
+                (16.0, "c".to_string()),
+                (24.0, "d".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_color_and_progress_split_runs() {
+        let mut glyphs = [
+            glyph('a', 0.0, 10.0),
+            glyph('b', 10.0, 10.0),
+            glyph('c', 20.0, 10.0),
+            glyph('d', 30.0, 10.0),
+        ];
+        glyphs[1].progress = 0.5;
+        glyphs[3].progress = 0.0;
+        assert_eq!(
+            texts(&glyphs),
+            vec![
+                (0.0, "a".to_string()),
+                (10.0, "b".to_string()),
+                (20.0, "c".to_string())
+            ]
+        );
+    }
+}
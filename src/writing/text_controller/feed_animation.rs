@@ -3,14 +3,14 @@ use super::super::*;
 
 #[derive(Event)]
 pub(in crate::writing) struct FeedWaitingEvent {
-    pub target_box_name: String,
+    pub target_box: Entity,
     pub wait_sec: f32,
 }
 
 #[derive(Event)]
 pub(in crate::writing) struct StartFeedingEvent {
-    pub target_box_name: String,
-    pub target_area_name: String,
+    pub target_box: Entity,
+    pub target_area: Entity,
 }
 
 #[derive(Component)]
@@ -39,8 +39,8 @@ pub(in crate::writing) fn setup_feed_starter(
     for event in waitting_event.read() {
         for (db_entity, wbs, DialogBox { name: db_name }) in &writing_query {
             for (ta_entity, ta, parent, tb_tf, tb_sp) in &text_box_query {
-                if event.target_box_name != *db_name || db_entity != parent.parent() {
-                    break;
+                if event.target_box != db_entity || db_entity != parent.parent() {
+                    continue;
                 }
                 match wbs {
                     WaitBrakerStyle::Auto { wait_sec: break_ws } => {
@@ -53,7 +53,7 @@ pub(in crate::writing) fn setup_feed_starter(
                         ..
                     } => {
                         let icon_opt = w_icon_query.iter().find(|x| {
-                            *db_name == x.1.target_box_name
+                            x.1.is_for(db_entity, db_name)
                                 && x.1.wait_for.contains(&WaitTarget::Feeding)
                         });
                         if let Some((ic_entity, _)) = icon_opt {
@@ -92,9 +92,9 @@ pub(in crate::writing) fn setup_feed_starter(
 pub(in crate::writing) fn trigger_feeding_by_event(
     mut commands: Commands,
     mut line_query: Query<(Entity, &ChildOf), With<MessageTextLine>>,
-    mut writing_query: Query<(&DialogBox, &mut DialogBoxPhase)>,
-    text_area_query: Query<(Entity, &TextArea, &FeedingStyle), With<Current>>,
-    mut icon_query: Query<(Entity, &mut Visibility), With<WaitingIcon>>,
+    mut writing_query: Query<(Entity, &DialogBox, &mut DialogBoxPhase)>,
+    text_area_query: Query<(Entity, &TextArea, &FeedingStyle, &ChildOf), With<Current>>,
+    mut icon_query: Query<(Entity, &mut Visibility, &WaitingIcon)>,
     mut start_feeding_event: EventWriter<StartFeedingEvent>,
     mut events: EventReader<BdsEvent>,
 ) {
@@ -106,9 +106,14 @@ pub(in crate::writing) fn trigger_feeding_by_event(
         {
             let db_opt = writing_query
                 .iter_mut()
-                .find(|x| x.0.name == target_db_name);
-            let ta_opt = text_area_query.iter().find(|x| x.1.name == target_ta_name);
-            if let (Some((db, mut dbp)), Some((ta_entity, ta, fs))) = (db_opt, ta_opt) {
+                .find(|x| event_wrapper.is_for(x.0, x.1.name == target_db_name));
+            let Some((db_entity, db, mut dbp)) = db_opt else {
+                continue;
+            };
+            let ta_opt = text_area_query
+                .iter()
+                .find(|x| x.3.parent() == db_entity && x.1.name == target_ta_name);
+            if let Some((ta_entity, _, fs, _)) = ta_opt {
                 *dbp = DialogBoxPhase::Typing;
                 for (l_entity, l_parent) in &mut line_query {
                     if l_parent.parent() == ta_entity {
@@ -116,11 +121,14 @@ pub(in crate::writing) fn trigger_feeding_by_event(
                         *dbp = DialogBoxPhase::WaitingAction;
                     }
                     start_feeding_event.write(StartFeedingEvent {
-                        target_box_name: db.name.clone(),
-                        target_area_name: ta.name.clone(),
+                        target_box: db_entity,
+                        target_area: ta_entity,
                     });
                 }
-                for (ic_entity, mut ic_vis) in &mut icon_query {
+                for (ic_entity, mut ic_vis, wi) in &mut icon_query {
+                    if !wi.is_for(db_entity, &db.name) {
+                        continue;
+                    }
                     *ic_vis = Visibility::Hidden;
                     commands.entity(ic_entity).remove::<TypingStyle>();
                     commands.entity(ic_entity).remove::<TypingTimer>();
@@ -130,23 +138,27 @@ pub(in crate::writing) fn trigger_feeding_by_event(
     }
 }
 
+#[allow(clippy::type_complexity)]
 pub(in crate::writing) fn trigger_feeding_by_time(
     mut commands: Commands,
-    mut writing_query: Query<(&DialogBox, &mut DialogBoxPhase)>,
+    mut writing_query: Query<(Entity, &mut DialogBoxPhase), With<DialogBox>>,
     mut text_area_query: Query<
-        (Entity, &TextArea, &FeedingStyle, &mut WaitFeedingTrigger),
-        With<Current>,
+        (Entity, &FeedingStyle, &mut WaitFeedingTrigger, &ChildOf),
+        (With<TextArea>, With<Current>),
     >,
     mut line_query: Query<Entity, With<MessageTextLine>>,
     parent_query: Query<&ChildOf>,
     mut start_feeding_event: EventWriter<StartFeedingEvent>,
     time: Res<Time>,
 ) {
-    for (db, mut dbp) in &mut writing_query {
-        if text_area_query.iter().len() > 0 {
+    for (db_entity, mut dbp) in &mut writing_query {
+        if text_area_query.iter().any(|x| x.3.parent() == db_entity) {
             *dbp = DialogBoxPhase::Typing;
         }
-        for (ta_entity, ta, fs, mut wft) in &mut text_area_query {
+        for (ta_entity, fs, mut wft, ta_parent) in &mut text_area_query {
+            if ta_parent.parent() != db_entity {
+                continue;
+            }
             if wft.timer.tick(time.delta()).finished() {
                 for l_entity in &mut line_query {
                     if parent_query.get(l_entity).ok().map(|x| x.parent()) == Some(ta_entity) {
@@ -156,8 +168,8 @@ pub(in crate::writing) fn trigger_feeding_by_time(
                 }
                 commands.entity(ta_entity).remove::<WaitFeedingTrigger>();
                 start_feeding_event.write(StartFeedingEvent {
-                    target_box_name: db.name.clone(),
-                    target_area_name: ta.name.clone(),
+                    target_box: db_entity,
+                    target_area: ta_entity,
                 });
             }
         }
@@ -166,19 +178,19 @@ pub(in crate::writing) fn trigger_feeding_by_time(
 
 pub(in crate::writing) fn start_feeding(
     mut commands: Commands,
-    mut window_query: Query<(&DialogBox, &mut DialogBoxPhase, &WaitBrakerStyle)>,
+    mut window_query: Query<(Entity, &DialogBox, &mut DialogBoxPhase, &WaitBrakerStyle)>,
     text_box_query: Query<(Entity, &TextArea, &GlobalTransform, &Sprite)>,
     line_query: Query<(Entity, &FeedingStyle, &ChildOf), With<MessageTextLine>>,
     mut start_feeding_event: EventReader<StartFeedingEvent>,
     type_registry: Res<AppTypeRegistry>,
 ) {
     for sf in start_feeding_event.read() {
-        for (db, mut ws, wbs) in &mut window_query {
-            if db.name != sf.target_box_name || *ws != DialogBoxPhase::WaitingAction {
+        for (db_entity, db, mut ws, wbs) in &mut window_query {
+            if db_entity != sf.target_box || *ws != DialogBoxPhase::WaitingAction {
                 continue;
             }
             for (ta_entity, ta, tb_tf, tb_sp) in &text_box_query {
-                if ta.name != sf.target_area_name {
+                if ta_entity != sf.target_area {
                     continue;
                 }
                 let target_lines = line_query
@@ -195,7 +207,7 @@ pub(in crate::writing) fn start_feeding(
                             size: fs_size,
                             sec: fs_sec,
                         } => {
-                            let line_size = line_query.iter().count();
+                            let line_size = target_lines.len();
                             let line_count = if *fs_size == 0 || line_size < *fs_size {
                                 0
                             } else {
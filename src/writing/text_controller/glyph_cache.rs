@@ -0,0 +1,291 @@
+use bevy::prelude::*;
+use rustybuzz::{shape, Face, UnicodeBuffer};
+use std::{
+    collections::{hash_map::DefaultHasher, hash_map::Entry, HashMap},
+    hash::{Hash, Hasher},
+};
+
+// 一文字分の形状です。glyph_idが0の文字はフォントに入っていません。
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(in crate::writing) struct CachedGlyph {
+    pub exists: bool,
+    pub x_advance: i32,
+}
+
+// Faceはフォントのバイト列を借りるので、読み込んだときにcmapの文字をすべて解析して残します。
+// cmapにない文字はmissing(.notdef)で書かれます。
+struct CachedFace {
+    height: f32,
+    glyphs: HashMap<char, CachedGlyph>,
+    missing: CachedGlyph,
+}
+
+impl CachedFace {
+    fn new(font: &Font) -> Option<Self> {
+        let face = Face::from_slice(&font.data, 0)?;
+        let mut glyphs = HashMap::new();
+        for subtable in face
+            .tables()
+            .cmap
+            .iter()
+            .flat_map(|cmap| cmap.subtables)
+            .filter(|s| s.is_unicode())
+        {
+            subtable.codepoints(|code| {
+                let Some(c) = char::from_u32(code) else {
+                    return;
+                };
+                let Some(id) = face.glyph_index(c) else {
+                    return;
+                };
+                glyphs.entry(c).or_insert(CachedGlyph {
+                    exists: id.0 != 0,
+                    x_advance: face.glyph_hor_advance(id).unwrap_or_default() as i32,
+                });
+            });
+        }
+        Some(CachedFace {
+            height: face.height() as f32,
+            glyphs,
+            missing: notdef(&face),
+        })
+    }
+}
+
+// どのフォントにも入っていない非文字を形にして、.notdefの幅を求めます。
+fn notdef(face: &Face) -> CachedGlyph {
+    let mut code = UnicodeBuffer::new();
+    code.push_str("\u{FFFF}");
+    let buffer = shape(face, &[], code);
+    CachedGlyph {
+        exists: false,
+        x_advance: buffer.glyph_positions().first().map_or(0, |p| p.x_advance),
+    }
+}
+
+// 同じフォントの並びを使うTextAreaで共有します。キーはフォントの並びのハッシュで、
+// 衝突したときはfontsを比べて作り直します。
+struct FallbackList {
+    fonts: Vec<AssetId<Font>>,
+    picked: HashMap<char, usize>,
+}
+
+// フォールバックのたびにFontを複製して解析し直さないように、
+// フォントごとの形状と、フォントの並びごとに文字がどのフォントで書かれるかを覚えます。
+#[derive(Resource, Default)]
+pub(in crate::writing) struct GlyphCache {
+    faces: HashMap<AssetId<Font>, CachedFace>,
+    fallbacks: HashMap<u64, FallbackList>,
+}
+
+impl GlyphCache {
+    // 読み込まれていないフォントはNoneです。
+    pub fn face_height(&mut self, handle: &Handle<Font>, fonts: &Assets<Font>) -> Option<f32> {
+        self.face(handle, fonts).map(|face| face.height)
+    }
+
+    pub fn glyph(
+        &mut self,
+        handle: &Handle<Font>,
+        target: char,
+        fonts: &Assets<Font>,
+    ) -> Option<CachedGlyph> {
+        let face = self.face(handle, fonts)?;
+        Some(*face.glyphs.get(&target).unwrap_or(&face.missing))
+    }
+
+    // targetを持つ最初のフォントを返します。どれにもなければ最後のフォントです。
+    pub fn choice_font<'a>(
+        &mut self,
+        list: &'a [TextFont],
+        target: char,
+        fonts: &Assets<Font>,
+    ) -> Option<&'a TextFont> {
+        let key = list_key(list);
+        let cached = self
+            .fallbacks
+            .get(&key)
+            .filter(|f| is_same_list(&f.fonts, list))
+            .and_then(|f| f.picked.get(&target));
+        if let Some(i) = cached {
+            return list.get(*i);
+        }
+        let mut is_loaded = true;
+        let mut index = None;
+        for (i, tf) in list.iter().enumerate() {
+            match self.glyph(&tf.font, target, fonts) {
+                Some(glyph) if glyph.exists => {
+                    index = Some(i);
+                    break;
+                }
+                Some(_) => (),
+                None => is_loaded = false,
+            }
+        }
+        let index = index.or(list.len().checked_sub(1))?;
+        // 読み込み中のフォントがあると結果が変わるので覚えません。
+        if is_loaded {
+            let fallback = self.fallbacks.entry(key).or_insert_with(|| FallbackList {
+                fonts: Vec::new(),
+                picked: HashMap::new(),
+            });
+            if !is_same_list(&fallback.fonts, list) {
+                fallback.fonts = list.iter().map(|tf| tf.font.id()).collect();
+                fallback.picked.clear();
+            }
+            fallback.picked.insert(target, index);
+        }
+        list.get(index)
+    }
+
+    fn face(&mut self, handle: &Handle<Font>, fonts: &Assets<Font>) -> Option<&CachedFace> {
+        let face = match self.faces.entry(handle.id()) {
+            Entry::Occupied(e) => e.into_mut(),
+            Entry::Vacant(e) => e.insert(CachedFace::new(fonts.get(handle)?)?),
+        };
+        Some(face)
+    }
+
+    fn forget(&mut self, id: AssetId<Font>) {
+        self.faces.remove(&id);
+        self.fallbacks.retain(|_, f| !f.fonts.contains(&id));
+    }
+}
+
+fn list_key(list: &[TextFont]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for tf in list {
+        tf.font.id().hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+fn is_same_list(ids: &[AssetId<Font>], list: &[TextFont]) -> bool {
+    ids.iter().copied().eq(list.iter().map(|tf| tf.font.id()))
+}
+
+pub(in crate::writing) fn invalidate_glyph_cache(
+    mut cache: ResMut<GlyphCache>,
+    mut events: EventReader<AssetEvent<Font>>,
+) {
+    for event in events.read() {
+        match event {
+            AssetEvent::Modified { id }
+            | AssetEvent::Removed { id }
+            | AssetEvent::Unused { id } => cache.forget(*id),
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod glyph_cache_tests {
+    use super::*;
+    use bevy::{ecs::system::RunSystemOnce, text::DEFAULT_FONT_DATA};
+
+    const AKABARA: &[u8] =
+        include_bytes!("../../ui_templates/rose_style/assets/fonts/赤薔薇/akabara-cinderella.ttf");
+
+    fn fonts() -> (Assets<Font>, Vec<TextFont>) {
+        let mut fonts = Assets::<Font>::default();
+        let list = [DEFAULT_FONT_DATA, AKABARA]
+            .into_iter()
+            .map(|data| TextFont {
+                font: fonts.add(Font::try_from_bytes(data.to_vec()).unwrap()),
+                ..default()
+            })
+            .collect();
+        (fonts, list)
+    }
+
+    fn shaped(font: &Font, target: char) -> CachedGlyph {
+        let face = Face::from_slice(&font.data, 0).unwrap();
+        let mut code = UnicodeBuffer::new();
+        code.push_str(&target.to_string());
+        let buffer = shape(&face, &[], code);
+        CachedGlyph {
+            exists: buffer.glyph_infos()[0].glyph_id != 0,
+            x_advance: buffer.glyph_positions()[0].x_advance,
+        }
+    }
+
+    #[test]
+    fn test_glyph_matches_shaping() {
+        let (fonts, list) = fonts();
+        let mut cache = GlyphCache::default();
+        for tf in &list {
+            let font = fonts.get(&tf.font).unwrap();
+            for c in "aZ !、あ漢ー\u{E000}".chars() {
+                assert_eq!(
+                    cache.glyph(&tf.font, c, &fonts),
+                    Some(shaped(font, c)),
+                    "{c}"
+                );
+            }
+        }
+        assert_eq!(cache.faces.len(), 2);
+    }
+
+    #[test]
+    fn test_choice_font_is_cached_per_list() {
+        let (mut fonts, list) = fonts();
+        let mut cache = GlyphCache::default();
+        let font_of = |tf: Option<&TextFont>| tf.map(|tf| tf.font.id());
+        assert_eq!(
+            font_of(cache.choice_font(&list, 'a', &fonts)),
+            Some(list[0].font.id())
+        );
+        assert_eq!(
+            font_of(cache.choice_font(&list, 'あ', &fonts)),
+            Some(list[1].font.id())
+        );
+        assert_eq!(cache.fallbacks.len(), 1);
+        // 並びが違えば別のものとして覚えます。
+        let reversed = [list[1].clone(), list[0].clone()];
+        assert_eq!(
+            font_of(cache.choice_font(&reversed, 'a', &fonts)),
+            Some(list[1].font.id())
+        );
+        assert_eq!(cache.fallbacks.len(), 2);
+        // 覚えた文字はフォントを見に行きません。
+        fonts.remove(&list[1].font);
+        cache.faces.clear();
+        assert_eq!(
+            font_of(cache.choice_font(&list, 'あ', &fonts)),
+            Some(list[1].font.id())
+        );
+        // 読み込まれていないフォントがあるあいだは覚えません。
+        assert_eq!(
+            font_of(cache.choice_font(&list, 'い', &fonts)),
+            Some(list[1].font.id())
+        );
+        assert!(!cache
+            .fallbacks
+            .values()
+            .any(|f| f.picked.contains_key(&'い')));
+    }
+
+    #[test]
+    fn test_forget_by_asset_event() {
+        let (fonts, list) = fonts();
+        let mut world = World::new();
+        world.init_resource::<Events<AssetEvent<Font>>>();
+        let mut cache = GlyphCache::default();
+        cache.choice_font(&list, 'あ', &fonts);
+        cache.choice_font(&list[..1], 'a', &fonts);
+        world.insert_resource(cache);
+        world.send_event(AssetEvent::Modified {
+            id: list[1].font.id(),
+        });
+        world.run_system_once(invalidate_glyph_cache).unwrap();
+        let cache = world.resource::<GlyphCache>();
+        assert!(!cache.faces.contains_key(&list[1].font.id()));
+        assert!(cache.faces.contains_key(&list[0].font.id()));
+        let remaining = cache
+            .fallbacks
+            .values()
+            .map(|f| f.fonts.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(remaining, vec![vec![list[0].font.id()]]);
+    }
+}
@@ -0,0 +1,619 @@
+use bevy::prelude::*;
+
+use super::*;
+use crate::writing::window_controller::popup::initialize_text_config;
+
+pub(in crate::writing) struct GlyphMetrics {
+    pub text_font: TextFont,
+    pub size: f32,
+    pub advance: f32,
+    pub kerning: f32,
+}
+
+// 文字の種類に割り当てられたフォントのうち、その文字を持つ最初のものを使います。
+// 割り当てがなければfont_settingsの順に探します。
+fn pick_font<'a>(
+    config: &'a TypeTextConfig,
+    target: char,
+    last_font: Option<&Handle<Font>>,
+    (fonts, cache): (&Assets<Font>, &mut GlyphCache),
+) -> Option<&'a TextFont> {
+    let mut has_glyph = |tf: &TextFont| {
+        cache
+            .glyph(&tf.font, target, fonts)
+            .is_some_and(|g| g.exists)
+    };
+    let Some(script) = CharScript::of(target) else {
+        let in_run = last_font
+            .filter(|_| config.keep_punctuation_in_run)
+            .and_then(|h| config.text_fonts.iter().find(|tf| tf.font == *h))
+            .filter(|tf| has_glyph(tf));
+        return in_run.or_else(|| cache.choice_font(&config.text_fonts, target, fonts));
+    };
+    let by_script = config
+        .text_fonts
+        .iter()
+        .zip(&config.font_scripts)
+        .filter(|(_, scripts)| scripts.contains(&script))
+        .map(|(tf, _)| tf)
+        .find(|tf| has_glyph(tf));
+    by_script.or_else(|| cache.choice_font(&config.text_fonts, target, fonts))
+}
+
+pub(in crate::writing) fn measure_glyph(
+    config: &TypeTextConfig,
+    target: char,
+    last_font: Option<&Handle<Font>>,
+    (fonts, cache): (&Assets<Font>, &mut GlyphCache),
+) -> Option<GlyphMetrics> {
+    let target_str = String::from(target);
+    let size_coefficient =
+        find_by_regex(target_str.clone(), &config.size_by_regulars).unwrap_or(1.0);
+    let kerning_coefficient = find_by_regex(target_str, &config.kerning_by_regulars).unwrap_or(0.0);
+    let font_text = pick_font(config, target, last_font, (fonts, &mut *cache))?;
+    let glyph = cache.glyph(&font_text.font, target, fonts)?;
+    let height = cache.face_height(&font_text.font, fonts)?;
+    let size = config.base_size * font_text.font_size * size_coefficient;
+    let pt_per_height = size / height;
+    Some(GlyphMetrics {
+        text_font: TextFont {
+            font: font_text.font.clone(),
+            font_size: size,
+            ..default()
+        },
+        size,
+        advance: pt_per_height * glyph.x_advance as f32,
+        kerning: size * kerning_coefficient,
+    })
+}
+
+/// How a page of a [`TextLayout`] ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PageBreak {
+    /// The script has a page feed here.
+    Explicit,
+    /// The text did not fit in the area and is fed implicitly.
+    Overflow,
+    /// The orders ran out.
+    End,
+}
+
+/// A line as it will be typed into a text area.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct LaidOutLine {
+    /// The characters on this line.
+    pub text: String,
+    /// The width of the line in pixels.
+    pub width: f32,
+    /// The largest glyph size on this line.
+    pub height: f32,
+}
+
+/// A page as it will be typed into a text area.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LaidOutPage {
+    /// The lines of the page from top to bottom.
+    pub lines: Vec<LaidOutLine>,
+    /// The pixel extents the page needs inside the text area.
+    pub size: Vec2,
+    /// What ended this page.
+    pub break_by: PageBreak,
+}
+
+/// The result of [`layout_orders`] and [`layout_script`].
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TextLayout {
+    /// The pages in typing order.
+    pub pages: Vec<LaidOutPage>,
+}
+
+impl TextLayout {
+    /// Returns `true` if any page is fed because the text did not fit in the area.
+    pub fn overflows(&self) -> bool {
+        self.pages.iter().any(|p| p.break_by == PageBreak::Overflow)
+    }
+
+    /// Returns the largest page extents.
+    pub fn max_size(&self) -> Vec2 {
+        self.pages.iter().fold(Vec2::ZERO, |acc, p| acc.max(p.size))
+    }
+}
+
+/// Lays out `orders` in a text area built from `config` without spawning any entities.
+///
+/// Orders are read in script order. Script events are skipped, so jumps and font size
+/// changes are not followed, and `${name}` is measured as an empty string. Returns `None` while the fonts of `config` are still loading.
+pub fn layout_orders(
+    orders: &[Order],
+    config: &TextAreaConfig,
+    asset_server: &AssetServer,
+    fonts: &Assets<Font>,
+) -> Option<TextLayout> {
+    let type_config = initialize_text_config(asset_server, config, 0);
+    let fonts = (fonts, &mut GlyphCache::default());
+    let variables = ScriptVariables::default();
+    let pages = layout_pages(
+        orders,
+        false,
+        &type_config,
+        fonts,
+        config.area_size,
+        None,
+        &variables,
+    )?;
+    Some(TextLayout { pages })
+}
+
+/// Parses `section` of `script` with `templates` and lays it out like [`layout_orders`].
+///
+/// Returns `None` if a template is invalid, the section does not exist or the fonts are
+/// still loading.
+pub fn layout_script<S: AsRef<str>>(
+    script: &str,
+    templates: &[S],
+    section: &str,
+    config: &TextAreaConfig,
+    asset_server: &AssetServer,
+    fonts: &Assets<Font>,
+) -> Option<TextLayout> {
+    let sections = read_script(script, templates).ok()?;
+    layout_orders(sections.get(section)?, config, asset_server, fonts)
+}
+
+// add_charとadd_empty_lineの折り返し・改ページの判定をなぞってページを組みます。
+// 改ページ後は改行待ちから始まるのでstarts_with_crは最初のページにだけ効きます。
+// フォントが読み込まれていない文字がある場合はNoneを返します。
+pub(in crate::writing) fn layout_pages<'a, I: IntoIterator<Item = &'a Order>>(
+    orders: I,
+    starts_with_cr: bool,
+    config: &TypeTextConfig,
+    (fonts, cache): (&Assets<Font>, &mut GlyphCache),
+    area_size: Vec2,
+    page_limit: Option<usize>,
+    variables: &ScriptVariables,
+) -> Option<Vec<LaidOutPage>> {
+    let mut pages = Vec::new();
+    let mut lines = if starts_with_cr {
+        vec![LaidOutLine::default()]
+    } else {
+        Vec::new()
+    };
+    let mut is_touched = false;
+    let mut pos_x = 0.0f32;
+    let mut last_font = None;
+    let is_overflow = |line_count: usize| line_count as f32 * config.base_size > area_size.y;
+    let finish_page = |pages: &mut Vec<LaidOutPage>, lines: Vec<LaidOutLine>, break_by| {
+        let width = lines.iter().fold(0.0f32, |acc, l| acc.max(l.width));
+        let size = Vec2::new(width, lines.len() as f32 * config.base_size);
+        pages.push(LaidOutPage {
+            lines,
+            size,
+            break_by,
+        });
+        page_limit.is_some_and(|limit| pages.len() >= limit)
+    };
+    for order in orders {
+        // ${name}は書くときと同じようにScriptVariablesの値に置き換えて測ります。
+        let text = match order {
+            Order::Text { text } => text.as_str(),
+            Order::Variable { name } => variables.get(name).unwrap_or_default(),
+            Order::CarriageReturn => {
+                pos_x = 0.0;
+                last_font = None;
+                is_touched = true;
+                if is_overflow(lines.len() + 1) {
+                    let page = std::mem::replace(&mut lines, vec![LaidOutLine::default()]);
+                    if finish_page(&mut pages, page, PageBreak::Overflow) {
+                        return Some(pages);
+                    }
+                } else {
+                    lines.push(LaidOutLine::default());
+                }
+                continue;
+            }
+            Order::PageFeed => {
+                pos_x = 0.0;
+                last_font = None;
+                is_touched = false;
+                let page = std::mem::replace(&mut lines, vec![LaidOutLine::default()]);
+                if finish_page(&mut pages, page, PageBreak::Explicit) {
+                    return Some(pages);
+                }
+                continue;
+            }
+            Order::ThroghEvent { .. } | Order::Command { .. } => continue,
+        };
+        for c in text.chars() {
+            let mut glyph = measure_glyph(config, c, last_font.as_ref(), (fonts, &mut *cache))?;
+            if lines.is_empty() {
+                lines.push(LaidOutLine::default());
+            }
+            let mut target_x = pos_x + glyph.size + glyph.kerning;
+            if target_x > area_size.x && pos_x > 0.0 {
+                // 行頭では前の文字のフォントを引き継ぎません。
+                glyph = measure_glyph(config, c, None, (fonts, &mut *cache))?;
+                if is_overflow(lines.len() + 1) {
+                    let page = std::mem::replace(&mut lines, vec![LaidOutLine::default()]);
+                    if finish_page(&mut pages, page, PageBreak::Overflow) {
+                        return Some(pages);
+                    }
+                } else {
+                    lines.push(LaidOutLine::default());
+                }
+                pos_x = 0.0;
+                target_x = glyph.size + glyph.kerning;
+            }
+            let next_x = pos_x + glyph.advance + glyph.kerning;
+            pos_x = if config.monospace { target_x } else { next_x };
+            last_font = Some(glyph.text_font.font.clone());
+            if let Some(line) = lines.last_mut() {
+                line.text.push(c);
+                line.width = line.width.max(target_x);
+                line.height = line.height.max(glyph.size);
+            }
+            is_touched = true;
+        }
+    }
+    if is_touched || pages.is_empty() {
+        finish_page(&mut pages, lines, PageBreak::End);
+    }
+    Some(pages)
+}
+
+// TextAreaはTopLeftなので、設定した領域の中心から大きさの半分だけ左上に置きます。
+fn fitted_origin(area_origin: Vec2, area_size: Vec2, size: Vec2) -> Vec2 {
+    let center = area_origin + Vec2::new(area_size.x, -area_size.y) / 2.0;
+    center + Vec2::new(-size.x, size.y) / 2.0
+}
+
+#[allow(clippy::type_complexity)]
+pub(in crate::writing) fn fit_area_to_page(
+    db_query: Query<(Entity, &LoadedScript, &TypingCursor, &DialogBoxPhase), With<DialogBox>>,
+    mut ta_query: Query<
+        (
+            Entity,
+            &AreaFitting,
+            &TypeTextConfig,
+            &mut Sprite,
+            &mut Transform,
+            &ChildOf,
+        ),
+        (With<TextArea>, With<Current>),
+    >,
+    mut bg_query: Query<&mut Sprite, (With<DialogBox>, Without<TextArea>)>,
+    line_query: Query<&ChildOf, With<MessageTextLine>>,
+    fonts: Res<Assets<Font>>,
+    mut glyph_cache: ResMut<GlyphCache>,
+    variables: Res<ScriptVariables>,
+) {
+    for (db_entity, script, cursor, phase) in &db_query {
+        let is_before_typing = matches!(
+            phase,
+            DialogBoxPhase::Preparing
+                | DialogBoxPhase::PoppingUp
+                | DialogBoxPhase::WaitToType
+                | DialogBoxPhase::Typing
+        );
+        let Some(order_list) = &script.order_list else {
+            continue;
+        };
+        if !is_before_typing {
+            continue;
+        }
+        for (ta_entity, fitting, config, mut ta_sp, mut ta_tf, parent) in &mut ta_query {
+            let AreaFitting::FitToPage {
+                min,
+                max,
+                box_margin,
+            } = fitting
+            else {
+                continue;
+            };
+            if parent.parent() != db_entity || line_query.iter().any(|l| l.parent() == ta_entity) {
+                continue;
+            }
+            let orders = cursor.pending.iter().chain(order_list.iter().rev());
+            let first_page = layout_pages(
+                orders,
+                cursor.in_cr,
+                config,
+                (&fonts, &mut glyph_cache),
+                *max,
+                Some(1),
+                &variables,
+            )
+            .and_then(|pages| pages.into_iter().next());
+            let Some(LaidOutPage { size: measured, .. }) = first_page else {
+                continue;
+            };
+            let size = measured.clamp(*min, *max);
+            if ta_sp.custom_size == Some(size) {
+                continue;
+            }
+            ta_sp.custom_size = Some(size);
+            let origin = fitted_origin(config.area_origin, config.area_size, size);
+            ta_tf.translation.x = origin.x;
+            ta_tf.translation.y = origin.y;
+            if let Ok(mut bg_sp) = bg_query.get_mut(db_entity) {
+                bg_sp.custom_size = Some(size + *box_margin);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod measure_tests {
+    use super::*;
+    use bevy::text::DEFAULT_FONT_DATA;
+
+    fn test_config(fonts: &mut Assets<Font>) -> TypeTextConfig {
+        let font = fonts.add(Font::try_from_bytes(DEFAULT_FONT_DATA.to_vec()).unwrap());
+        TypeTextConfig {
+            text_fonts: vec![TextFont {
+                font,
+                font_size: 1.0,
+                ..default()
+            }],
+            font_scripts: vec![vec![]],
+            keep_punctuation_in_run: false,
+            kerning_by_regulars: default(),
+            size_by_regulars: default(),
+            text_color: default(),
+            writing: WritingStyle::Put,
+            base_size: 20.0,
+            typing_timing: TypingTiming::ByPage,
+            layer: default(),
+            horizon_alignment: AlignHorizon::Left,
+            vertical_alignment: AlignVertical::Top,
+            monospace: false,
+            pos_z: 0.0,
+            rendering: default(),
+            area_origin: Vec2::ZERO,
+            area_size: Vec2::new(1000.0, 100.0),
+        }
+    }
+
+    fn text(s: &str) -> Order {
+        Order::Text {
+            text: s.to_string(),
+        }
+    }
+
+    fn layout(
+        orders: &[Order],
+        config: &TypeTextConfig,
+        fonts: &Assets<Font>,
+        area_size: Vec2,
+        page_limit: Option<usize>,
+        variables: &ScriptVariables,
+    ) -> Vec<LaidOutPage> {
+        let mut cache = GlyphCache::default();
+        let fonts = (fonts, &mut cache);
+        layout_pages(
+            orders, false, config, fonts, area_size, page_limit, variables,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_layout_measures_variables() {
+        let mut fonts = Assets::<Font>::default();
+        let config = test_config(&mut fonts);
+        let mut variables = ScriptVariables::default();
+        variables.set("hero", "bcd");
+        let area = Vec2::new(1000.0, 100.0);
+        let with_variable = [
+            text("a"),
+            Order::Variable {
+                name: "hero".to_string(),
+            },
+        ];
+        let pages = layout(&with_variable, &config, &fonts, area, None, &variables);
+        let expected = layout(&[text("abcd")], &config, &fonts, area, None, &variables);
+        assert_eq!(pages, expected);
+        assert_eq!(pages[0].lines[0].text, "abcd");
+        let unset = ScriptVariables::default();
+        let pages = layout(&with_variable, &config, &fonts, area, None, &unset);
+        assert_eq!(pages[0].lines[0].text, "a");
+    }
+
+    fn texts(pages: &[LaidOutPage]) -> Vec<Vec<&str>> {
+        pages
+            .iter()
+            .map(|p| p.lines.iter().map(|l| l.text.as_str()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_layout_breaks_on_overflow() {
+        let mut fonts = Assets::<Font>::default();
+        let config = test_config(&mut fonts);
+        let variables = ScriptVariables::default();
+        // base_sizeが20なので、高さ40には2行まで入ります。
+        let area = Vec2::new(1000.0, 40.0);
+        let orders = [
+            text("a"),
+            Order::CarriageReturn,
+            text("b"),
+            Order::CarriageReturn,
+            text("c"),
+        ];
+        let pages = layout(&orders, &config, &fonts, area, None, &variables);
+        assert_eq!(texts(&pages), vec![vec!["a", "b"], vec!["c"]]);
+        assert_eq!(pages[0].break_by, PageBreak::Overflow);
+        assert_eq!(pages[1].break_by, PageBreak::End);
+        assert_eq!(pages[0].size.y, 40.0);
+        assert_eq!(pages[1].size.y, 20.0);
+    }
+
+    #[test]
+    fn test_layout_wraps_long_lines() {
+        let mut fonts = Assets::<Font>::default();
+        let mut config = test_config(&mut fonts);
+        // 等幅なら一文字ずつbase_sizeだけ進むので、幅50には2文字まで入ります。
+        config.monospace = true;
+        let variables = ScriptVariables::default();
+        let area = Vec2::new(50.0, 40.0);
+        let pages = layout(&[text("abcdef")], &config, &fonts, area, None, &variables);
+        assert_eq!(texts(&pages), vec![vec!["ab", "cd"], vec!["ef"]]);
+        assert_eq!(pages[0].break_by, PageBreak::Overflow);
+        assert_eq!(pages[0].size, Vec2::new(40.0, 40.0));
+        assert!(TextLayout { pages }.overflows());
+    }
+
+    #[test]
+    fn test_layout_breaks_on_page_feed() {
+        let mut fonts = Assets::<Font>::default();
+        let config = test_config(&mut fonts);
+        let variables = ScriptVariables::default();
+        let area = Vec2::new(1000.0, 100.0);
+        let orders = [text("a"), Order::PageFeed, text("b")];
+        let pages = layout(&orders, &config, &fonts, area, None, &variables);
+        assert_eq!(texts(&pages), vec![vec!["a"], vec!["b"]]);
+        assert_eq!(pages[0].break_by, PageBreak::Explicit);
+        assert_eq!(pages[1].break_by, PageBreak::End);
+        assert!(!TextLayout { pages }.overflows());
+        // 最後の改ページの後に空のページは作りません。
+        let orders = [text("a"), Order::PageFeed];
+        let pages = layout(&orders, &config, &fonts, area, None, &variables);
+        assert_eq!(texts(&pages), vec![vec!["a"]]);
+        assert_eq!(pages[0].break_by, PageBreak::Explicit);
+    }
+
+    #[test]
+    fn test_layout_of_nothing_is_one_empty_page() {
+        let mut fonts = Assets::<Font>::default();
+        let config = test_config(&mut fonts);
+        let variables = ScriptVariables::default();
+        let area = Vec2::new(1000.0, 100.0);
+        let pages = layout(&[], &config, &fonts, area, None, &variables);
+        assert_eq!(pages.len(), 1);
+        assert!(pages[0].lines.is_empty());
+        assert_eq!(pages[0].size, Vec2::ZERO);
+        assert_eq!(pages[0].break_by, PageBreak::End);
+    }
+
+    #[test]
+    fn test_layout_stops_at_page_limit() {
+        let mut fonts = Assets::<Font>::default();
+        let config = test_config(&mut fonts);
+        let variables = ScriptVariables::default();
+        let area = Vec2::new(1000.0, 20.0);
+        let orders = [
+            text("a"),
+            Order::CarriageReturn,
+            text("b"),
+            Order::PageFeed,
+            text("c"),
+        ];
+        let pages = layout(&orders, &config, &fonts, area, Some(1), &variables);
+        assert_eq!(texts(&pages), vec![vec!["a"]]);
+        assert_eq!(pages[0].break_by, PageBreak::Overflow);
+        let pages = layout(&orders, &config, &fonts, area, Some(2), &variables);
+        assert_eq!(texts(&pages), vec![vec!["a"], vec!["b"]]);
+        assert_eq!(pages[1].break_by, PageBreak::Explicit);
+        let pages = layout(&orders, &config, &fonts, area, None, &variables);
+        assert_eq!(pages.len(), 3);
+    }
+
+    #[test]
+    fn test_layout_orders_waits_for_fonts() {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, AssetPlugin::default()))
+            .init_asset::<Font>();
+        let asset_server = app.world().resource::<AssetServer>();
+        let fonts = app.world().resource::<Assets<Font>>();
+        let config = TextAreaConfig::default();
+        assert_eq!(
+            layout_orders(&[text("a")], &config, asset_server, fonts),
+            None
+        );
+        // 文字がなければフォントを見ません。
+        let layout = layout_orders(&[], &config, asset_server, fonts).unwrap();
+        assert_eq!(layout.pages.len(), 1);
+        assert_eq!(layout.max_size(), Vec2::ZERO);
+    }
+
+    #[test]
+    fn test_fitted_origin_keeps_center() {
+        let origin = Vec2::new(-600.0, 80.0);
+        let area = Vec2::new(1060.0, 260.0);
+        let fitted = fitted_origin(origin, area, Vec2::new(200.0, 60.0));
+        assert_eq!(fitted, Vec2::new(-170.0, -20.0));
+        assert_eq!(fitted_origin(origin, area, area), origin);
+    }
+
+    #[test]
+    fn test_fitted_origin_of_centered_area() {
+        let area = Vec2::new(400.0, 100.0);
+        let size = Vec2::new(120.0, 40.0);
+        let fitted = fitted_origin(Vec2::new(-200.0, 50.0), area, size);
+        assert_eq!(fitted, Vec2::new(-60.0, 20.0));
+    }
+
+    // 先にLatinだけのFiraMono、後に仮名と漢字を持つ赤薔薇を並べます。
+    fn two_font_config(fonts: &mut Assets<Font>) -> (TypeTextConfig, Handle<Font>, Handle<Font>) {
+        let mut config = test_config(fonts);
+        let fira = config.text_fonts[0].font.clone();
+        let akabara_data = include_bytes!(
+            "../../ui_templates/rose_style/assets/fonts/赤薔薇/akabara-cinderella.ttf"
+        );
+        let akabara = fonts.add(Font::try_from_bytes(akabara_data.to_vec()).unwrap());
+        config.text_fonts.push(TextFont {
+            font: akabara.clone(),
+            font_size: 1.0,
+            ..default()
+        });
+        config.font_scripts = vec![
+            vec![CharScript::Latin],
+            vec![CharScript::Kana, CharScript::Han],
+        ];
+        config.keep_punctuation_in_run = true;
+        (config, fira, akabara)
+    }
+
+    fn picked(
+        config: &TypeTextConfig,
+        target: char,
+        last_font: Option<&Handle<Font>>,
+        fonts: &Assets<Font>,
+    ) -> Handle<Font> {
+        let mut cache = GlyphCache::default();
+        pick_font(config, target, last_font, (fonts, &mut cache))
+            .unwrap()
+            .font
+            .clone()
+    }
+
+    #[test]
+    fn test_pick_font_by_script() {
+        let mut fonts = Assets::<Font>::default();
+        let (config, fira, akabara) = two_font_config(&mut fonts);
+        assert_eq!(picked(&config, 'a', None, &fonts), fira);
+        assert_eq!(picked(&config, 'あ', None, &fonts), akabara);
+        assert_eq!(picked(&config, '漢', Some(&fira), &fonts), akabara);
+        // 文字の種類があれば、前の文字のフォントは引き継ぎません。
+        assert_eq!(picked(&config, 'a', Some(&akabara), &fonts), fira);
+    }
+
+    #[test]
+    fn test_pick_font_keeps_punctuation_in_run() {
+        let mut fonts = Assets::<Font>::default();
+        let (config, fira, akabara) = two_font_config(&mut fonts);
+        assert_eq!(picked(&config, '!', Some(&akabara), &fonts), akabara);
+        assert_eq!(picked(&config, ' ', Some(&akabara), &fonts), akabara);
+        assert_eq!(picked(&config, '!', Some(&fira), &fonts), fira);
+    }
+
+    #[test]
+    fn test_pick_font_falls_back_from_run() {
+        let mut fonts = Assets::<Font>::default();
+        let (mut config, fira, akabara) = two_font_config(&mut fonts);
+        // 前のフォントに字形がなければ、並び順で字形を持つものを探します。
+        assert_eq!(picked(&config, '、', Some(&fira), &fonts), akabara);
+        // 行頭には前の文字がありません。
+        assert_eq!(picked(&config, '!', None, &fonts), fira);
+        config.keep_punctuation_in_run = false;
+        assert_eq!(picked(&config, '!', Some(&akabara), &fonts), fira);
+    }
+}
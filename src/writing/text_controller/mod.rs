@@ -4,14 +4,19 @@ use bevy::{
     render::view::{RenderLayers, Visibility},
     sprite::Anchor,
 };
-use rustybuzz::Face;
 
+pub(super) mod batched;
 pub(super) mod feed_animation;
+pub(super) mod glyph_cache;
+pub(super) mod measure;
 pub(super) mod typing_animations;
 
 use super::*;
 use crate::utility::*;
+use batched::*;
 use feed_animation::*;
+use glyph_cache::*;
+use measure::*;
 
 #[derive(Component)]
 #[require(Sprite)]
@@ -24,6 +29,13 @@ pub(in crate::writing) struct MessageTextLine {
 #[require(TypingTimer, Text2d, RenderLayers, WritingStyle)]
 pub(in crate::writing) struct MessageTextChar;
 
+// 溢れて書けなかった文字と、改行待ちかどうかをDialogBoxごとに持ちます。
+#[derive(Component, Default, Debug)]
+pub(in crate::writing) struct TypingCursor {
+    pub pending: Option<Order>,
+    pub in_cr: bool,
+}
+
 #[derive(Component, Default, Clone, Debug)]
 pub(super) struct TypingTimer {
     pub timer: Timer,
@@ -38,6 +50,8 @@ pub(super) struct LastChar {
     pub entity: Option<Entity>,
     pub pos: CharPos,
     pub timer: TypingTimer,
+    // keep_punctuation_in_runで使う、行の最後の文字のフォントです。
+    pub font: Option<Handle<Font>>,
 }
 
 #[derive(SystemParam, Debug)]
@@ -60,6 +74,7 @@ type LineData = (
     &'static Transform,
     &'static Sprite,
     &'static ChildOf,
+    Option<&'static BatchedLine>,
 );
 
 #[derive(SystemParam, Debug)]
@@ -69,6 +84,7 @@ pub(super) struct CurrentTextAreaQuery<'w, 's> {
 
 type AreaData = (
     Entity,
+    &'static TextArea,
     &'static Sprite,
     &'static TypeTextConfig,
     &'static ChildOf,
@@ -76,21 +92,32 @@ type AreaData = (
 
 pub(in crate::writing) fn add_new_text(
     mut commands: Commands,
-    mut writing_query: Query<(Entity, &DialogBox, &mut LoadedScript, &mut DialogBoxPhase)>,
+    mut writing_query: Query<
+        (
+            Entity,
+            &DialogBox,
+            &mut LoadedScript,
+            &mut DialogBoxPhase,
+            &mut TypingCursor,
+        ),
+        With<DialogBox>,
+    >,
     text_area_query: CurrentTextAreaQuery,
     last_data: CurrentQuery,
     app_type_registry: Res<AppTypeRegistry>,
+    command_registry: Res<DialogCommandRegistry>,
+    variables: Res<ScriptVariables>,
     mut wrapper: EventWriter<BdsEvent>,
     mut ps_event: EventWriter<FeedWaitingEvent>,
+    mut error_event: EventWriter<DialogScriptError>,
     fonts_res: Res<Assets<Font>>,
-    mut pending: Local<Option<Order>>,
-    mut in_cr: Local<bool>,
+    mut glyph_cache: ResMut<GlyphCache>,
 ) {
-    for (w_ent, DialogBox { name: w_name }, mut script, mut dbp) in &mut writing_query {
+    for (w_ent, db, mut script, mut dbp, mut cursor) in &mut writing_query {
         if *dbp != DialogBoxPhase::Typing {
             continue;
         }
-        for (tb_ent, tb_spr, config, parent) in &text_area_query.area {
+        for (tb_ent, ta, tb_spr, config, parent) in &text_area_query.area {
             if w_ent != parent.parent() {
                 continue;
             }
@@ -100,52 +127,152 @@ pub(in crate::writing) fn add_new_text(
                 y: height,
             } = tb_spr.custom_size.unwrap_or_default();
             loop {
-                let next_order = get_next_order(&pending, &mut script.order_list, *in_cr);
+                let next_order =
+                    match get_next_order(&cursor.pending, &mut script.order_list, cursor.in_cr) {
+                        // 命令の数が変わらないよう、変数はその場で文字に置き換えます。
+                        Some(Order::Variable { name }) => Some(Order::Text {
+                            text: variables.get(&name).unwrap_or_default().to_string(),
+                        }),
+                        order => order,
+                    };
                 match next_order {
-                    Some(Order::Type {
-                        character: new_word,
-                    }) => {
-                        let fonts = fonts_res.as_ref();
-                        let char_config = (config, &mut last_char, fonts, width, last_line_opt);
-                        if add_char(&mut commands, new_word, char_config) {
-                            *pending = None;
-                            *in_cr = false;
-                        } else {
-                            *pending = next_order;
-                            *in_cr = true;
-                        };
+                    Some(Order::Text { text }) => {
+                        cursor.pending = None;
+                        // 入りきらなかった文字から後ろは改行してから打ち直します。
+                        for (i, new_word) in text.char_indices() {
+                            let fonts = (fonts_res.as_ref(), glyph_cache.as_mut());
+                            let char_config = (config, &mut last_char, fonts, width, last_line_opt);
+                            if add_char(&mut commands, new_word, char_config) {
+                                cursor.in_cr = false;
+                            } else {
+                                cursor.pending = Some(Order::Text {
+                                    text: text[i..].to_string(),
+                                });
+                                cursor.in_cr = true;
+                                break;
+                            };
+                        }
                     }
                     Some(Order::CarriageReturn) => {
                         let line_config = (config, &mut last_char, height, &mut last_line_opt);
                         if add_empty_line(&mut commands, line_config, tb_ent) {
-                            *in_cr = false;
+                            cursor.in_cr = false;
                         } else {
-                            send_feed_event(&mut ps_event, w_name, &last_char, &mut dbp);
-                            *in_cr = true;
+                            send_feed_event(&mut ps_event, w_ent, &last_char, &mut dbp);
+                            cursor.in_cr = true;
                             break;
                         };
                     }
                     Some(Order::PageFeed) => {
-                        send_feed_event(&mut ps_event, w_name, &last_char, &mut dbp);
-                        *in_cr = true;
+                        send_feed_event(&mut ps_event, w_ent, &last_char, &mut dbp);
+                        cursor.in_cr = true;
                         break;
                     }
                     Some(Order::ThroghEvent { ron: r }) => {
-                        let event_opt = read_ron(&app_type_registry, r);
-                        if let Ok(reflect_value) = event_opt {
-                            wrapper.write(BdsEvent {
-                                value: reflect_value,
-                            });
-                        }
+                        let event_opt = read_ron(&app_type_registry, &r)
+                            .map_err(|e| {
+                                let kind = ScriptErrorKind::InvalidEvent(e.code.to_string());
+                                error_event.write(script_error(kind, w_ent, &script, &r));
+                            })
+                            .ok();
+                        send_script_event(
+                            event_opt,
+                            &mut wrapper,
+                            (db, w_ent, ta, tb_ent),
+                            &script,
+                        );
                         break;
                     }
-                    None => break,
+                    Some(Order::Command { name, args }) => {
+                        let snippet = command_snippet(&name, &args);
+                        let event_opt = match command_registry.to_ron(&name, &args) {
+                            Some(r) => read_ron(&app_type_registry, r)
+                                .map_err(|e| {
+                                    let kind = ScriptErrorKind::InvalidEvent(e.code.to_string());
+                                    error_event.write(script_error(kind, w_ent, &script, &snippet));
+                                })
+                                .ok(),
+                            None => {
+                                let kind = ScriptErrorKind::UnknownCommand;
+                                error_event.write(script_error(kind, w_ent, &script, &snippet));
+                                None
+                            }
+                        };
+                        send_script_event(
+                            event_opt,
+                            &mut wrapper,
+                            (db, w_ent, ta, tb_ent),
+                            &script,
+                        );
+                        break;
+                    }
+                    Some(Order::Variable { .. }) | None => break,
                 }
             }
         }
     }
 }
 
+fn send_script_event(
+    event_opt: Option<Box<dyn PartialReflect>>,
+    wrapper: &mut EventWriter<BdsEvent>,
+    (db, w_ent, ta, tb_ent): (&DialogBox, Entity, &TextArea, Entity),
+    script: &LoadedScript,
+) {
+    if let Some(reflect_value) = event_opt {
+        let remaining = script.order_list.as_ref().map_or(0, Vec::len);
+        let location = ScriptLocation {
+            path: script
+                .bds_handle_opt
+                .as_ref()
+                .and_then(|h| h.path())
+                .map(|p| p.without_label().to_string())
+                .unwrap_or_default(),
+            section: script.target_section.clone(),
+            index: script.section_len.saturating_sub(remaining + 1),
+        };
+        wrapper.write(BdsEvent {
+            value: reflect_value,
+            dialog_box: Some(w_ent),
+            source: BdsSource {
+                dialog_box_name: db.name.clone(),
+                text_area: Some(tb_ent),
+                text_area_name: ta.name.clone(),
+                location: Some(location),
+            },
+        });
+    }
+}
+
+// エラーに載せるため、スクリプトに書かれた形へ戻します。
+fn command_snippet(name: &str, args: &[(String, String)]) -> String {
+    let args = args.iter().map(|(k, v)| format!(" {k}={v}"));
+    format!("[@{name}{}]", args.collect::<String>())
+}
+
+// raw_ordersは読み込み時に調べられないので、ここで見つかったものは行と列が分かりません。
+fn script_error(
+    kind: ScriptErrorKind,
+    w_ent: Entity,
+    script: &LoadedScript,
+    snippet: &str,
+) -> DialogScriptError {
+    DialogScriptError {
+        kind,
+        dialog_box: Some(w_ent),
+        path: script
+            .bds_handle_opt
+            .as_ref()
+            .and_then(|h| h.path())
+            .map(|p| p.without_label().to_string())
+            .unwrap_or_default(),
+        section: script.target_section.clone(),
+        line: 0,
+        column: 0,
+        snippet: snippet.trim().to_string(),
+    }
+}
+
 // Currentを取ってるので総ざらいする必要はない
 pub(in crate::writing) fn initialize_typing_data(
     last_data: &CurrentQuery,
@@ -157,16 +284,24 @@ pub(in crate::writing) fn initialize_typing_data(
     let mut last_text_list = last_data.text.iter();
     let last_text_data_opt = last_text_list.find(|x| Some(x.5.parent()) == last_line_opt);
     let last_text_opt = last_text_data_opt.map(|x| x.0);
+    // まとめて描く行では文字のEntityがないので、行に持たせた最後の文字を使います。
+    let last_glyph_opt = last_line_data_opt
+        .and_then(|l| l.4)
+        .map(|b| (b.remaining_secs(), b.glyphs.last()));
     let last_timer = TypingTimer {
         timer: Timer::from_seconds(
             last_text_data_opt
                 .map(|x| x.4.timer.remaining_secs())
+                .or(last_glyph_opt.map(|x| x.0))
                 .unwrap_or_default(),
             TimerMode::Once,
         ),
     };
     let last_x = last_text_data_opt
         .map(|t| t.3.font_size + t.1.translation.x)
+        .or(last_glyph_opt
+            .and_then(|x| x.1)
+            .map(|g| g.font.font_size + g.x))
         .unwrap_or_default();
     let last_y = last_line_data_opt
         .map(|l| l.1.translation.y)
@@ -175,22 +310,28 @@ pub(in crate::writing) fn initialize_typing_data(
         x: last_x,
         y: last_y,
     };
+    let last_font = last_text_data_opt
+        .map(|t| t.3.font.clone())
+        .or(last_glyph_opt
+            .and_then(|x| x.1)
+            .map(|g| g.font.font.clone()));
     let last_char = LastChar {
         entity: last_text_opt,
         pos: char_pos,
         timer: last_timer,
+        font: last_font,
     };
     (last_line_opt, last_char)
 }
 
 fn send_feed_event(
     fw_event: &mut EventWriter<FeedWaitingEvent>,
-    name: &str,
+    db_entity: Entity,
     last_char: &LastChar,
     dbp: &mut DialogBoxPhase,
 ) {
     fw_event.write(FeedWaitingEvent {
-        target_box_name: name.to_string(),
+        target_box: db_entity,
         wait_sec: last_char.timer.timer.remaining_secs(),
     });
     *dbp = DialogBoxPhase::WaitingAction;
@@ -215,48 +356,21 @@ fn add_char(
     (config, last_char, font_assets, width, last_line_opt): (
         &TypeTextConfig,
         &mut LastChar,
-        &Assets<Font>,
+        (&Assets<Font>, &mut GlyphCache),
         f32,
         Option<Entity>,
     ),
 ) -> bool {
-    let new_str = String::from(new_word);
-    let size_coefficient = find_by_regex(new_str.clone(), &config.size_by_regulars).unwrap_or(1.0);
-    let kerning_coefficient = find_by_regex(new_str, &config.kerning_by_regulars).unwrap_or(0.0);
-    let Some(font_text) = choice_font(&config.text_fonts, new_word, font_assets) else {
-        return false;
-    };
-    let Some(font) = &font_assets.get(&font_text.font) else {
+    let last_font = last_char.font.as_ref();
+    let Some(glyph) = measure_glyph(config, new_word, last_font, font_assets) else {
         return false;
     };
-    let Some(glyph_buffer) = get_glyph_buffer(font, new_word) else {
-        return false;
-    };
-    let Some(positions) = &glyph_buffer.glyph_positions().iter().next() else {
-        return false;
-    };
-    let Some(face) = Face::from_slice(&font.data, 0) else {
-        return false;
-    };
-    let true_size = config.base_size * font_text.font_size * size_coefficient;
-    let kerning = true_size * kerning_coefficient;
-    let target_x = last_char.pos.x + true_size + kerning;
+    let true_size = glyph.size;
+    let target_x = last_char.pos.x + true_size + glyph.kerning;
     if target_x > width {
         false
     } else {
-        let text_font = TextFont {
-            font: font_text.font.clone(),
-            font_size: true_size,
-            ..Default::default()
-        };
-        let text2d_bundle = (
-            Text2d::new(new_word.to_string()),
-            Transform::from_translation(Vec3::new(last_char.pos.x, 0.0, 0.0)),
-            Visibility::Hidden,
-            Anchor::BottomLeft,
-            text_font,
-            config.text_color,
-        );
+        let char_x = last_char.pos.x;
         let last_secs = last_char.timer.timer.remaining_secs();
         let type_sec = match config.typing_timing {
             TypingTiming::ByChar { sec: s } => last_secs + s,
@@ -273,11 +387,33 @@ fn add_char(
         let typing_timer = TypingTimer {
             timer: Timer::from_seconds(type_sec, TimerMode::Once),
         };
-        let pt_per_height = true_size / face.height() as f32;
-        let advance = pt_per_height * positions.x_advance as f32;
-        let next_x = last_char.pos.x + advance + kerning;
+        let next_x = last_char.pos.x + glyph.advance + glyph.kerning;
         last_char.pos.x = if config.monospace { target_x } else { next_x };
         last_char.timer = typing_timer.clone();
+        last_char.font = Some(glyph.text_font.font.clone());
+        if config.rendering == GlyphRendering::Batched {
+            let Some(last_line) = last_line_opt else {
+                return false;
+            };
+            let glyph_config = (config, &typing_timer);
+            push_glyph(
+                commands,
+                last_line,
+                new_word,
+                (char_x, glyph.advance),
+                glyph.text_font,
+                glyph_config,
+            );
+            return true;
+        }
+        let text2d_bundle = (
+            Text2d::new(new_word.to_string()),
+            Transform::from_translation(Vec3::new(char_x, 0.0, 0.0)),
+            Visibility::Hidden,
+            Anchor::BottomLeft,
+            glyph.text_font,
+            config.text_color,
+        );
         let new_char = (
             MessageTextChar,
             typing_timer,
@@ -311,6 +447,7 @@ fn add_empty_line(
 ) -> bool {
     last_char.pos.x = 0.;
     last_char.pos.y -= config.base_size;
+    last_char.font = None;
     if last_char.pos.y < -min_height {
         false
     } else {
@@ -329,6 +466,10 @@ fn add_empty_line(
             },
         );
         let new_line_entity = commands.spawn((new_line, Current)).id();
+        if config.rendering == GlyphRendering::Batched {
+            let batched = BatchedLine::new(config.layer.clone());
+            commands.entity(new_line_entity).insert(batched);
+        }
         if let Some(last_line) = last_line_opt {
             commands.entity(*last_line).remove::<Current>();
         }
@@ -341,7 +482,10 @@ fn add_empty_line(
 // Todo:位置を自由にラインどりできるようにしたい
 pub(in crate::writing) fn settle_lines(
     dialogbox_query: Query<(Entity, &DialogBoxPhase), With<DialogBox>>,
-    mut text_lines: Query<(&MessageTextLine, &mut Transform), Without<MessageTextChar>>,
+    mut text_lines: Query<
+        (&MessageTextLine, &mut Transform, Option<&BatchedLine>),
+        Without<MessageTextChar>,
+    >,
     text_char: Query<(&TextFont, &Transform), With<MessageTextChar>>,
     area_sprite_query: Query<&mut Sprite, With<TextArea>>,
     mut line_sprite_query: Query<&mut Sprite, Without<TextArea>>,
@@ -360,17 +504,24 @@ pub(in crate::writing) fn settle_lines(
                 continue;
             };
             for tl_entity in tl_entities {
-                let Ok((mtl, mut l_tf)) = text_lines.get_mut(*tl_entity) else {
+                let Ok((mtl, mut l_tf, batched)) = text_lines.get_mut(*tl_entity) else {
                     continue;
                 };
                 let Ok(mut tl_spr) = line_sprite_query.get_mut(*tl_entity) else {
                     continue;
                 };
-                let Ok(tx_entities) = children_query.get(*tl_entity) else {
-                    continue;
-                };
                 let mut text_size_list: Vec<f32> = Vec::new();
                 let mut last_pos_x = 0.0;
+                for glyph in batched.iter().flat_map(|b| b.glyphs.iter()) {
+                    text_size_list.push(glyph.font.font_size);
+                    last_pos_x = f32::max(last_pos_x, glyph.x + glyph.font.font_size);
+                }
+                let has_glyphs = batched.is_some_and(|b| !b.glyphs.is_empty());
+                let tx_entities = match children_query.get(*tl_entity) {
+                    Ok(children) => &children[..],
+                    Err(_) if has_glyphs => &[],
+                    Err(_) => continue,
+                };
                 for tx_entity in tx_entities {
                     let Ok((text_font, t_tf)) = text_char.get(*tx_entity) else {
                         continue;
@@ -405,7 +556,7 @@ pub(in crate::writing) fn settle_lines(
                 if *phase != DialogBoxPhase::Typing {
                     continue;
                 }
-                if let Ok((mtl, mut l_tf)) = text_lines.get_mut(*tl_entity) {
+                if let Ok((mtl, mut l_tf, _)) = text_lines.get_mut(*tl_entity) {
                     l_tf.translation.y -= match mtl.vertical_alignment {
                         AlignVertical::Center => (area_height + prev_height) / 2.0,
                         AlignVertical::Bottom => area_height + prev_height,
@@ -416,3 +567,55 @@ pub(in crate::writing) fn settle_lines(
         }
     }
 }
+
+#[cfg(test)]
+mod text_controller_tests {
+    use super::*;
+    use crate::writing::test_app::*;
+
+    #[derive(Resource, Default)]
+    struct Indices(Vec<usize>);
+
+    fn record_indices(mut events: EventReader<BdsEvent>, mut indices: ResMut<Indices>) {
+        for event in events.read() {
+            if let Some(location) = &event.source.location {
+                indices.0.push(location.index);
+            }
+        }
+    }
+
+    #[test]
+    fn test_variable_does_not_shift_index() {
+        let mut app = test_app();
+        app.init_resource::<Indices>()
+            .add_systems(Update, record_indices);
+        app.world_mut()
+            .resource_mut::<ScriptVariables>()
+            .set("empty", "");
+        let signal = Order::Command {
+            name: "signal".to_string(),
+            args: vec![("signal".to_string(), "\"after\"".to_string())],
+        };
+        let variable = Order::Variable {
+            name: "empty".to_string(),
+        };
+        // 命令は後ろから取り出されます。
+        app.world_mut()
+            .send_event(raw_dialog(vec![signal, variable]));
+        run(&mut app, 5);
+        assert_eq!(app.world().resource::<Indices>().0, vec![1]);
+    }
+
+    #[test]
+    fn test_command_snippet_keeps_args() {
+        let args = vec![
+            ("power".to_string(), "3".to_string()),
+            ("target".to_string(), "\"camera\"".to_string()),
+        ];
+        assert_eq!(
+            command_snippet("shake", &args),
+            "[@shake power=3 target=\"camera\"]"
+        );
+        assert_eq!(command_snippet("feed", &[]), "[@feed]");
+    }
+}
@@ -0,0 +1,183 @@
+use bevy::{
+    input::{
+        keyboard::{Key, KeyboardInput},
+        ButtonState,
+    },
+    prelude::*,
+    sprite::Anchor,
+    window::{Ime, PrimaryWindow},
+};
+
+use super::*;
+
+/// Asks the player to enter a string: `[@input variable="hero" max_len=8]`.
+///
+/// The dialog box stops typing until the entry is confirmed with Enter, then the string
+/// is stored in [`ScriptVariables`] as `variable` and can be written as `${hero}`.
+#[derive(Reflect, Default, Debug)]
+#[reflect(Default)]
+pub struct InputText {
+    pub variable: String,
+    /// The maximum number of characters. `0` means no limit.
+    #[reflect(default)]
+    pub max_len: usize,
+}
+
+/// Sent when the player confirms a string asked by [`InputText`].
+#[derive(Event, Debug, Clone)]
+pub struct TextInputConfirmed {
+    pub dialog_box: Entity,
+    pub variable: String,
+    pub value: String,
+}
+
+// 入力中の文字列です。preeditはIMEで変換中の文字列で、確定するまでvalueに入りません。
+// IMEで文字を打ち始めたら、文字はIme::Commitからだけ受け取ります。
+// 確定したフレームは残しておき、確定のキーで選択肢や文字送りが動かないようにします。
+#[derive(Component)]
+pub(in crate::writing) struct TextEntry {
+    dialog_box: Entity,
+    variable: String,
+    max_len: usize,
+    value: String,
+    preedit: String,
+    uses_ime: bool,
+    is_confirmed: bool,
+}
+
+impl TextEntry {
+    fn push_str(&mut self, s: &str) {
+        for c in s.chars().filter(|c| !c.is_control()) {
+            if self.max_len != 0 && self.value.chars().count() >= self.max_len {
+                break;
+            }
+            self.value.push(c);
+        }
+    }
+}
+
+// CurrentのTextAreaの最後の行の下に入力欄を出します。
+#[allow(clippy::type_complexity)]
+pub(in crate::writing) fn open_text_entry(
+    mut commands: Commands,
+    mut db_query: Query<(Entity, &mut DialogBoxPhase, Has<Current>), With<DialogBox>>,
+    ta_query: Query<(Entity, &TypeTextConfig, &ChildOf), (With<TextArea>, With<Current>)>,
+    last_data: CurrentQuery,
+    mut window_query: Query<&mut Window, With<PrimaryWindow>>,
+    mut events: EventReader<BdsEvent>,
+) {
+    for event_wrapper in events.read() {
+        let Some(InputText { variable, max_len }) = event_wrapper.get::<InputText>() else {
+            continue;
+        };
+        for (db_entity, mut dbp, is_current) in &mut db_query {
+            if !event_wrapper.is_for(db_entity, is_current) {
+                continue;
+            }
+            let Some((ta_entity, config, _)) = ta_query.iter().find(|x| x.2.parent() == db_entity)
+            else {
+                continue;
+            };
+            let (_, last_char) = initialize_typing_data(&last_data, ta_entity);
+            let entry = TextEntry {
+                dialog_box: db_entity,
+                variable: variable.clone(),
+                max_len,
+                value: String::new(),
+                preedit: String::new(),
+                uses_ime: false,
+                is_confirmed: false,
+            };
+            commands.spawn((
+                entry,
+                Text2d::new("_"),
+                config.text_fonts.first().cloned().unwrap_or_default(),
+                config.text_color,
+                Anchor::BottomLeft,
+                Transform::from_xyz(0.0, last_char.pos.y - config.base_size, config.pos_z),
+                config.layer.clone(),
+                ChildOf(ta_entity),
+            ));
+            *dbp = DialogBoxPhase::Fixed;
+            if let Ok(mut window) = window_query.single_mut() {
+                window.ime_enabled = true;
+            }
+        }
+    }
+}
+
+pub(in crate::writing) fn edit_text_entry(
+    mut commands: Commands,
+    mut entry_query: Query<(Entity, &mut TextEntry, &mut Text2d)>,
+    mut db_query: Query<&mut DialogBoxPhase>,
+    mut window_query: Query<&mut Window, With<PrimaryWindow>>,
+    mut key_events: EventReader<KeyboardInput>,
+    mut ime_events: EventReader<Ime>,
+    mut confirmed_event: EventWriter<TextInputConfirmed>,
+    mut variables: ResMut<ScriptVariables>,
+) {
+    let ime_list = ime_events.read().collect::<Vec<_>>();
+    let key_list = key_events
+        .read()
+        .filter(|x| x.state == ButtonState::Pressed)
+        .collect::<Vec<_>>();
+    for (entry_entity, mut entry, mut text) in &mut entry_query {
+        if entry.is_confirmed {
+            commands.entity(entry_entity).despawn();
+            continue;
+        }
+        for ime in &ime_list {
+            match ime {
+                Ime::Preedit { value, .. } => {
+                    entry.uses_ime |= !value.is_empty();
+                    entry.preedit = value.clone();
+                }
+                Ime::Commit { value, .. } => {
+                    entry.uses_ime = true;
+                    entry.preedit.clear();
+                    entry.push_str(value);
+                }
+                _ => (),
+            }
+        }
+        let mut is_confirmed = false;
+        // 変換中のキー入力はIMEが受け取るので無視します。
+        for key in &key_list {
+            if !entry.preedit.is_empty() {
+                break;
+            }
+            match &key.logical_key {
+                Key::Enter => is_confirmed = true,
+                Key::Backspace => {
+                    entry.value.pop();
+                }
+                Key::Space if !entry.uses_ime => entry.push_str(" "),
+                Key::Character(c) if !entry.uses_ime => entry.push_str(c),
+                _ => (),
+            }
+        }
+        if !is_confirmed {
+            text.0 = format!("{}{}_", entry.value, entry.preedit);
+            continue;
+        }
+        variables.set(entry.variable.clone(), entry.value.clone());
+        confirmed_event.write(TextInputConfirmed {
+            dialog_box: entry.dialog_box,
+            variable: entry.variable.clone(),
+            value: entry.value.clone(),
+        });
+        if let Ok(mut dbp) = db_query.get_mut(entry.dialog_box) {
+            *dbp = DialogBoxPhase::Typing;
+        }
+        if let Ok(mut window) = window_query.single_mut() {
+            window.ime_enabled = false;
+        }
+        entry.is_confirmed = true;
+        text.0.clear();
+    }
+}
+
+// 入力欄があるあいだは、確定したフレームも含めて選択肢や文字送りの入力を受け付けません。
+pub(in crate::writing) fn no_text_entry(entry_query: Query<(), With<TextEntry>>) -> bool {
+    entry_query.is_empty()
+}
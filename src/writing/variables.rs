@@ -0,0 +1,157 @@
+use bevy::prelude::*;
+use std::collections::HashMap;
+
+use super::events::BdsEvent;
+
+/// Game state that scripts can read, such as conditions of choices.
+///
+/// Every value is kept as a string. Numbers are compared as numbers when both sides parse.
+#[derive(Resource, Default, Debug, Clone)]
+pub struct ScriptVariables {
+    values: HashMap<String, String>,
+}
+
+impl ScriptVariables {
+    /// Sets `name` to `value`.
+    pub fn set(&mut self, name: impl Into<String>, value: impl ToString) {
+        self.values.insert(name.into(), value.to_string());
+    }
+
+    /// Returns the value of `name`.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.values.get(name).map(String::as_str)
+    }
+
+    /// Removes `name` and returns its value.
+    pub fn remove(&mut self, name: &str) -> Option<String> {
+        self.values.remove(name)
+    }
+
+    /// Evaluates a condition written in a script.
+    ///
+    /// - `name` is true if `name` is set to anything but `""`, `"0"` or `"false"`.
+    /// - `!condition` negates a condition.
+    /// - `name == value`, `name != value`, `name >= value`, `name <= value`,
+    ///   `name > value` and `name < value` compare `name` with a literal.
+    ///
+    /// An empty condition is always true.
+    pub fn evaluate(&self, condition: &str) -> bool {
+        let condition = condition.trim();
+        if condition.is_empty() {
+            return true;
+        }
+        if let Some(negated) = condition.strip_prefix('!') {
+            if !negated.starts_with('=') {
+                return !self.evaluate(negated);
+            }
+        }
+        // 2文字の演算子を先に探します。
+        for op in ["==", "!=", ">=", "<=", ">", "<"] {
+            if let Some((name, literal)) = condition.split_once(op) {
+                let value = self.get(name.trim()).unwrap_or_default();
+                let literal = literal.trim().trim_matches('"');
+                return compare(value, op, literal);
+            }
+        }
+        self.get(condition)
+            .is_some_and(|v| !matches!(v, "" | "0" | "false"))
+    }
+}
+
+fn compare(value: &str, op: &str, literal: &str) -> bool {
+    if let (Ok(v), Ok(l)) = (value.parse::<f64>(), literal.parse::<f64>()) {
+        return match op {
+            "==" => v == l,
+            "!=" => v != l,
+            ">=" => v >= l,
+            "<=" => v <= l,
+            ">" => v > l,
+            _ => v < l,
+        };
+    }
+    match op {
+        "==" => value == literal,
+        "!=" => value != literal,
+        _ => false,
+    }
+}
+
+//-----
+
+/// Sets a [`ScriptVariables`] value from a script: `[@set name="has_key" value="true"]`.
+#[derive(Reflect, Default, Debug)]
+pub struct SetVariable {
+    pub name: String,
+    pub value: String,
+}
+
+pub(in crate::writing) fn set_variable(
+    mut events: EventReader<BdsEvent>,
+    mut variables: ResMut<ScriptVariables>,
+) {
+    for event_wrapper in events.read() {
+        if let Some(SetVariable { name, value }) = event_wrapper.get::<SetVariable>() {
+            variables.set(name, value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod variables_tests {
+    use super::*;
+
+    fn variables() -> ScriptVariables {
+        let mut v = ScriptVariables::default();
+        v.set("gold", 10);
+        v.set("name", "Fox");
+        v.set("has_key", true);
+        v.set("is_open", "false");
+        v.set("count", 0);
+        v.set("empty", "");
+        v
+    }
+
+    #[test]
+    fn test_evaluate_truthiness() {
+        let v = variables();
+        assert!(v.evaluate(""));
+        assert!(v.evaluate("  "));
+        assert!(v.evaluate("has_key"));
+        assert!(v.evaluate("gold"));
+        assert!(!v.evaluate("is_open"));
+        assert!(!v.evaluate("count"));
+        assert!(!v.evaluate("empty"));
+        assert!(!v.evaluate("missing"));
+        assert!(v.evaluate("!missing"));
+        assert!(!v.evaluate("!has_key"));
+        assert!(v.evaluate("!!has_key"));
+    }
+
+    #[test]
+    fn test_evaluate_comparisons() {
+        let v = variables();
+        assert!(v.evaluate("gold == 10"));
+        assert!(v.evaluate("gold==10.0"));
+        assert!(v.evaluate("gold != 9"));
+        assert!(v.evaluate("gold >= 10"));
+        assert!(v.evaluate("gold <= 10"));
+        assert!(!v.evaluate("gold > 10"));
+        assert!(!v.evaluate("gold < 10"));
+        assert!(v.evaluate("gold > 9.5"));
+        assert!(v.evaluate("name == \"Fox\""));
+        assert!(v.evaluate("name != Wolf"));
+        assert!(!v.evaluate("missing == 0"));
+        assert!(v.evaluate("missing == \"\""));
+    }
+
+    #[test]
+    fn test_compare_falls_back_to_strings() {
+        assert!(compare("10", "==", "10.0"));
+        assert!(compare("abc", "==", "abc"));
+        assert!(compare("abc", "!=", "abd"));
+        assert!(!compare("abc", ">", "abb"));
+        assert!(!compare("abc", "<=", "abc"));
+        assert!(compare("2", "<", "10"));
+        assert!(!compare("2", ">=", "x"));
+    }
+}
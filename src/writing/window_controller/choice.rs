@@ -1,76 +1,164 @@
 use super::*;
+use crate::writing::input::{DisabledChoice, PushedByCommand};
 use crate::writing::*;
 use bevy::render::view::RenderLayers;
 
-// Todo: 名前の重複を防ぐ機構を入れた方がいいかもしれない
 #[derive(Component)]
 pub(in crate::writing) struct ChoiceBoxState {
+    pub(super) main_box: Entity,
     main_writing_name: String,
     text_area_names: Vec<String>,
     choice_box_name: String,
     target_list: Vec<(String, String)>,
+    slots: Vec<ChoiceSlot>,
     select_vector: SelectVector,
     sinkdown: SinkDownType,
     background_scaling_per_button: Vec2,
     background_scaling_anchor: Anchor,
 }
 
+impl ChoiceBoxState {
+    // index番目のボタンが、スクリプトの何番目の選択肢かを返します。
+    // 頁送りのボタンと、時間切れで何も選ばれなかったときの範囲外のindexはNoneです。
+    fn made_choice(&self, index: usize) -> Option<(usize, &str)> {
+        let script_index = self.slots.get(index)?.script_index?;
+        let (label, _) = self.target_list.get(index)?;
+        Some((script_index, label))
+    }
+}
+
+// 並んだ選択肢ひとつ分です。script_indexはスクリプトに書かれた順番で、頁送りはNoneです。
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct ChoiceSlot {
+    enabled: bool,
+    script_index: Option<usize>,
+}
+
 #[derive(Event, Default, Reflect)]
+#[reflect(Default)]
 pub(in crate::writing) struct SetupChoice {
     target_list: Vec<(String, String)>,
+    // target_listと同じ順に並びます。足りない分は条件なしとして扱います。
+    #[reflect(default)]
+    conditions: Vec<ChoiceCondition>,
+    // ChoiceOverflow::Pagingで何頁目を開くかです。
+    #[reflect(default)]
+    page: usize,
+}
+
+// 条件はScriptVariables::evaluateで評価します。空なら常に真です。
+#[derive(Reflect, Default, Debug, Clone)]
+pub(in crate::writing) struct ChoiceCondition {
+    #[reflect(default)]
+    show_if: String,
+    #[reflect(default)]
+    enable_if: String,
 }
 
+// 頁送りの選択肢です。次の頁のSetupChoiceを呼び出し元のスクリプトの次に差し込みます。
+#[derive(Reflect, Default)]
+pub(in crate::writing) struct TurnChoicePage {
+    setup: String,
+}
+
+// ChoiceOverflow::Extendで足りない分を複製したChoiceButtonです。閉じたら消します。
+#[derive(Component)]
+pub(in crate::writing) struct ExtraChoiceButton;
+
 #[derive(Reflect, Default)]
 pub(in crate::writing) struct ChoosenEvent {
     pub choosen_event: String,
     pub choice_box_name: String,
+    pub index: usize,
 }
 
 #[derive(Component)]
 pub(in crate::writing) struct Choosable;
 
+// Choosableになってから数え始めます。
+#[derive(Component)]
+pub(in crate::writing) struct ChoiceCountdown {
+    timer: Timer,
+    fallback: TimeoutChoice,
+    bar: Entity,
+}
+
 #[allow(clippy::type_complexity)]
 pub(in crate::writing) fn open_choice_box(
     mut commands: Commands,
-    mut db_query: Query<
-        (&ChoiceBoxConfig, &mut DialogBoxPhase, &Children, &DialogBox),
-        With<Current>,
-    >,
+    mut db_query: Query<(
+        Entity,
+        &ChoiceBoxConfig,
+        &mut DialogBoxPhase,
+        &Children,
+        &DialogBox,
+        Has<Current>,
+    )>,
     mut vis_query: Query<&mut Visibility>,
     cc_query: Query<Entity, (With<ChoiceBoxState>, With<Current>)>,
-    cb_query: Query<(Entity, &ChoiceButton)>,
+    cb_query: Query<(Entity, &ChoiceButton), Without<ExtraChoiceButton>>,
     bg_query: Query<(Entity, &DialogBoxBackground)>,
     mut sp_query: Query<&mut Sprite>,
     mut tf_query: Query<&mut Transform>,
     mut events: EventReader<BdsEvent>,
     setup_config: Res<SetupConfig>,
+    variables: Res<ScriptVariables>,
+    app_type_registry: Res<AppTypeRegistry>,
     mut ow_event: EventWriter<OpenDialog>,
+    mut lifecycle_event: EventWriter<DialogLifecycle>,
 ) {
     for event_wrapper in events.read() {
-        if let Some(SetupChoice { target_list: tl }) = event_wrapper.get::<SetupChoice>() {
-            if let Ok((cbc, mut dbs, children, db)) = db_query.single_mut() {
-                let bg_opt = bg_query
-                    .iter()
-                    .find(|x| x.1.writing_name == cbc.choice_box_name);
-                let background_entity = if let Some((entity, _)) = bg_opt {
+        if let Some(SetupChoice {
+            target_list: base_list,
+            conditions,
+            page,
+        }) = event_wrapper.get::<SetupChoice>()
+        {
+            let next_page = SetupChoice {
+                target_list: base_list.clone(),
+                conditions: conditions.clone(),
+                page: page + 1,
+            };
+            let next_page_ron = write_ron(&app_type_registry, next_page)
+                .and_then(|setup| write_ron(&app_type_registry, TurnChoicePage { setup }))
+                .unwrap_or_default();
+            let filtered = filter_choices(base_list, &conditions, &variables);
+            // 全部隠れたときは選択肢を開かず、そのまま次の命令に進みます。
+            if filtered.0.is_empty() {
+                continue;
+            }
+            for (db_entity, cbc, mut dbs, children, db, is_current) in &mut db_query {
+                if !event_wrapper.is_for(db_entity, is_current) {
+                    continue;
+                }
+                let (tl, slots, culled_tas) =
+                    fit_choices_to_areas(cbc, filtered.clone(), page, &next_page_ron);
+                let bg_opt = cbc.choice_box.or_else(|| {
+                    bg_query
+                        .iter()
+                        .find(|x| x.1.writing_name == cbc.choice_box_name)
+                        .map(|x| x.0)
+                });
+                let background_entity = if let Some(entity) = bg_opt {
                     entity
                 } else {
                     commands.spawn(Sprite::default()).id()
                 };
                 let button_entities = cb_query
                     .iter()
-                    .filter(|x| x.1.target_box_name == cbc.choice_box_name)
+                    .filter(|x| x.1.is_for(background_entity, &cbc.choice_box_name))
                     .map(|x| x.0)
                     .collect::<Vec<_>>();
-                let culled_tas = &cbc.button_text_areas[0..tl.len()];
                 let ta_names = culled_tas
                     .iter()
                     .map(|c| c.area_name.clone())
                     .collect::<Vec<_>>();
                 let cs = ChoiceBoxState {
+                    main_box: db_entity,
                     main_writing_name: db.name.clone(),
                     choice_box_name: cbc.choice_box_name.clone(),
                     target_list: tl.clone(),
+                    slots: slots.clone(),
                     select_vector: cbc.select_vector,
                     sinkdown: cbc.sinkdown,
                     text_area_names: ta_names.clone(),
@@ -84,6 +172,26 @@ pub(in crate::writing) fn open_choice_box(
                     .entity(background_entity)
                     .insert(cs)
                     .add_children(&button_entities);
+                if let Some(limit) = &cbc.time_limit {
+                    let bar = commands
+                        .spawn((
+                            Sprite {
+                                color: limit.bar_color,
+                                custom_size: Some(limit.bar_size),
+                                anchor: Anchor::CenterLeft,
+                                ..default()
+                            },
+                            Transform::from_translation(limit.bar_offset.extend(1.0)),
+                            RenderLayers::layer(setup_config.render_layer.into()),
+                            ChildOf(background_entity),
+                        ))
+                        .id();
+                    commands.entity(background_entity).insert(ChoiceCountdown {
+                        timer: Timer::from_seconds(limit.sec, TimerMode::Once),
+                        fallback: limit.fallback,
+                        bar,
+                    });
+                }
                 if let Ok(mut vis) = vis_query.get_mut(background_entity) {
                     *vis = Visibility::Hidden;
                 }
@@ -96,6 +204,32 @@ pub(in crate::writing) fn open_choice_box(
                         .custom_size
                         .map(|Vec2 { x, y }| Vec2::new(x + x_expand, y + y_expand));
                 }
+                if let ChoiceOverflow::Extend { step } = overflow_of(cbc) {
+                    let template = cb_query
+                        .iter_many(&button_entities)
+                        .max_by_key(|x| x.1.sort_number);
+                    if let Some((template_entity, template_button)) = template {
+                        let template_tf =
+                            tf_query.get(template_entity).copied().unwrap_or_default();
+                        let slide = Vec2::new(x_dir * x_expand / 2.0, y_dir * y_expand / 2.0);
+                        for k in 1..=tl.len().saturating_sub(button_entities.len()) {
+                            let mut tf = template_tf;
+                            tf.translation += (step * k as f32 + slide).extend(0.0);
+                            let button = ChoiceButton {
+                                sort_number: template_button.sort_number + k,
+                                ..template_button.clone()
+                            };
+                            commands.entity(template_entity).clone_and_spawn().insert((
+                                tf,
+                                button,
+                                ExtraChoiceButton,
+                                Visibility::Inherited,
+                                RenderLayers::layer(setup_config.render_layer.into()),
+                                ChildOf(background_entity),
+                            ));
+                        }
+                    }
+                }
                 for (i, entity) in button_entities.iter().enumerate() {
                     if let Ok(mut vis) = vis_query.get_mut(*entity) {
                         if i >= tl.len() {
@@ -114,16 +248,26 @@ pub(in crate::writing) fn open_choice_box(
                 }
                 let slided_text_area_configs = culled_tas
                     .iter()
-                    .map(|base| TextAreaConfig {
+                    .zip(&slots)
+                    .map(|(base, slot)| TextAreaConfig {
                         area_origin: Vec2::new(
                             base.area_origin.x + x_dir * x_expand / 2.0,
                             base.area_origin.y + y_dir * y_expand / 2.0,
                         ),
+                        text_config: CharConfig {
+                            font_color: if slot.enabled {
+                                base.text_config.font_color
+                            } else {
+                                cbc.disabled_color
+                            },
+                            ..base.text_config.clone()
+                        },
                         ..base.clone()
                     })
                     .collect::<Vec<_>>();
                 let opening_event = OpenDialog {
                     writing_name: cbc.choice_box_name.clone(),
+                    target: Some(background_entity),
                     raw_orders: make_choice_order(&tl, &cbc.choice_box_name, &ta_names),
                     popup: cbc.popup,
                     text_area_configs: slided_text_area_configs,
@@ -133,6 +277,14 @@ pub(in crate::writing) fn open_choice_box(
                     ..default()
                 };
                 ow_event.write(opening_event);
+                lifecycle_event.write(DialogLifecycle {
+                    dialog_box: db_entity,
+                    writing_name: db.name.clone(),
+                    stage: LifecycleStage::ChoiceOpened {
+                        choice_box: background_entity,
+                        labels: tl.iter().map(|x| x.0.clone()).collect(),
+                    },
+                });
                 *dbs = DialogBoxPhase::Fixed;
                 for childe in children {
                     commands.entity(*childe).insert(Pending {
@@ -144,6 +296,126 @@ pub(in crate::writing) fn open_choice_box(
     }
 }
 
+// button_text_areasに収まらない選択肢を、ChoiceOverflowに従って頁に分けるかTextAreaを増やして収めます。
+fn fit_choices_to_areas(
+    cbc: &ChoiceBoxConfig,
+    (target_list, slots): (Vec<(String, String)>, Vec<ChoiceSlot>),
+    page: usize,
+    next_page_ron: &str,
+) -> (Vec<(String, String)>, Vec<ChoiceSlot>, Vec<TextAreaConfig>) {
+    let capacity = cbc.button_text_areas.len();
+    if capacity == 0 {
+        return (Vec::new(), Vec::new(), Vec::new());
+    }
+    if target_list.len() <= capacity {
+        let areas = cbc.button_text_areas[0..target_list.len()].to_vec();
+        return (target_list, slots, areas);
+    }
+    match overflow_of(cbc) {
+        ChoiceOverflow::Paging { next_label } => {
+            // 最後の枠は頁送りに使います。
+            let per_page = capacity - 1;
+            let start = page % target_list.len().div_ceil(per_page) * per_page;
+            let mut paged = target_list
+                .into_iter()
+                .skip(start)
+                .take(per_page)
+                .collect::<Vec<_>>();
+            let mut paged_slots = slots
+                .into_iter()
+                .skip(start)
+                .take(per_page)
+                .collect::<Vec<_>>();
+            paged.push((next_label, next_page_ron.to_string()));
+            paged_slots.push(ChoiceSlot {
+                enabled: true,
+                script_index: None,
+            });
+            let areas = cbc.button_text_areas[0..paged.len()].to_vec();
+            (paged, paged_slots, areas)
+        }
+        ChoiceOverflow::Extend { step } => {
+            let areas = (0..target_list.len())
+                .map(|i| extended_area(&cbc.button_text_areas, i, step))
+                .collect();
+            (target_list, slots, areas)
+        }
+    }
+}
+
+// 枠が一つだけでは頁送りを置けないので、枠の高さずつ下に増やします。
+fn overflow_of(cbc: &ChoiceBoxConfig) -> ChoiceOverflow {
+    match (&cbc.overflow, cbc.button_text_areas.as_slice()) {
+        (ChoiceOverflow::Paging { .. }, [area]) => ChoiceOverflow::Extend {
+            step: Vec2::new(0.0, -area.area_size.y),
+        },
+        (overflow, _) => overflow.clone(),
+    }
+}
+
+fn extended_area(base: &[TextAreaConfig], i: usize, step: Vec2) -> TextAreaConfig {
+    if let Some(area) = base.get(i) {
+        return area.clone();
+    }
+    let last = &base[base.len() - 1];
+    let offset = i + 1 - base.len();
+    TextAreaConfig {
+        area_name: numbered_name(&last.area_name, offset),
+        area_origin: last.area_origin + step * offset as f32,
+        ..last.clone()
+    }
+}
+
+// "Button Area 02"のような末尾の数字を増やします。数字がなければ後ろに付けます。
+fn numbered_name(name: &str, offset: usize) -> String {
+    let head = name.trim_end_matches(|c: char| c.is_ascii_digit());
+    let digits = &name[head.len()..];
+    match digits.parse::<usize>() {
+        Ok(n) => format!("{head}{:0width$}", n + offset, width = digits.len()),
+        Err(_) => format!("{name} {offset}"),
+    }
+}
+
+pub(in crate::writing) fn turn_choice_page(
+    mut events: EventReader<BdsEvent>,
+    mut ls_query: Query<&mut LoadedScript>,
+) {
+    for event_wrapper in events.read() {
+        if let Some(TurnChoicePage { setup }) = event_wrapper.get::<TurnChoicePage>() {
+            let Some(db_entity) = event_wrapper.dialog_box else {
+                continue;
+            };
+            if let Ok(mut ls) = ls_query.get_mut(db_entity) {
+                ls.order_list
+                    .get_or_insert_with(Vec::new)
+                    .push(Order::ThroghEvent { ron: setup });
+            }
+        }
+    }
+}
+
+// show_ifを満たさない選択肢を除き、残りにenable_ifの結果を添えます。
+fn filter_choices(
+    target_list: Vec<(String, String)>,
+    conditions: &[ChoiceCondition],
+    variables: &ScriptVariables,
+) -> (Vec<(String, String)>, Vec<ChoiceSlot>) {
+    target_list
+        .into_iter()
+        .enumerate()
+        .filter_map(|(i, target)| {
+            let condition = conditions.get(i).cloned().unwrap_or_default();
+            let slot = ChoiceSlot {
+                enabled: variables.evaluate(&condition.enable_if),
+                script_index: Some(i),
+            };
+            variables
+                .evaluate(&condition.show_if)
+                .then_some((target, slot))
+        })
+        .unzip()
+}
+
 fn get_slide_direction(anchor: Anchor) -> (f32, f32) {
     let mut x_direction = 0.0;
     let mut y_direction = 0.0;
@@ -181,18 +453,18 @@ fn make_choice_order(
         script.push_str(&format!("{header}{writing_name}{midpoint}{name}{footer}"));
         script.push_str(text);
     }
-    Some(parse_script(&script, &[""], ""))
+    parse_script(&script, &[""], "")
 }
 
 pub(in crate::writing) fn setup_choice(
     mut commands: Commands,
-    cb_query: Query<(Entity, &ChoiceBoxState, &Children, &DialogBoxPhase), With<Current>>,
+    cb_query: Query<(Entity, &ChoiceBoxState, &Children, &DialogBoxPhase)>,
     ta_query: Query<(Entity, &TextArea, &GlobalTransform, &Sprite), Without<Selective>>,
     app_type_registry: Res<AppTypeRegistry>,
 ) {
-    if let Ok((cb_entity, cbs, children, dbp)) = cb_query.single() {
+    for (cb_entity, cbs, children, dbp) in &cb_query {
         if *dbp != DialogBoxPhase::Typing {
-            return;
+            continue;
         }
         for (i, ta_name) in cbs.text_area_names.iter().enumerate() {
             let target = cbs
@@ -203,6 +475,7 @@ pub(in crate::writing) fn setup_choice(
             let ron_base = ChoosenEvent {
                 choosen_event: target,
                 choice_box_name: cbs.choice_box_name.clone(),
+                index: i,
             };
             let ron = write_ron(&app_type_registry, ron_base).unwrap_or_default();
             for (ta_entity, ta, tf, sp) in ta_query.iter_many(children) {
@@ -216,7 +489,11 @@ pub(in crate::writing) fn setup_choice(
                         key_vector: cbs.select_vector,
                         number: i,
                     };
-                    commands.entity(ta_entity).insert((wa, se));
+                    if cbs.slots.get(i).is_none_or(|slot| slot.enabled) {
+                        commands.entity(ta_entity).insert((wa, se));
+                    } else {
+                        commands.entity(ta_entity).insert((wa, se, DisabledChoice));
+                    }
                 }
             }
         }
@@ -224,6 +501,60 @@ pub(in crate::writing) fn setup_choice(
     }
 }
 
+#[allow(clippy::type_complexity)]
+pub(in crate::writing) fn count_down_choice(
+    mut commands: Commands,
+    mut cb_query: Query<
+        (Entity, &ChoiceBoxState, &mut ChoiceCountdown, &Children),
+        With<Choosable>,
+    >,
+    ta_query: Query<
+        (Entity, &Selective, Has<Selected>),
+        (With<WaitInputGo>, Without<Pending>, Without<DisabledChoice>),
+    >,
+    mut tf_query: Query<&mut Transform>,
+    time: Res<Time>,
+) {
+    for (cb_entity, cbs, mut countdown, children) in &mut cb_query {
+        countdown.timer.tick(time.delta());
+        if let Ok(mut tf) = tf_query.get_mut(countdown.bar) {
+            tf.scale.x = countdown.timer.fraction_remaining();
+        }
+        if !countdown.timer.finished() {
+            continue;
+        }
+        let mut candidates = ta_query.iter_many(children).collect::<Vec<_>>();
+        candidates.sort_by_key(|x| x.1.number);
+        let fallback = match countdown.fallback {
+            TimeoutChoice::Selected => candidates.iter().find(|x| x.2),
+            TimeoutChoice::Index(i) => candidates.iter().find(|x| {
+                cbs.slots
+                    .get(x.1.number)
+                    .is_some_and(|slot| slot.script_index == Some(i))
+            }),
+        };
+        if let Some((ta_entity, ..)) = fallback.or(candidates.first()) {
+            commands.entity(*ta_entity).insert(PushedByCommand);
+        } else {
+            // 選べるものがなければ、何も選ばずに閉じてスクリプトの続きに進みます。
+            let none = ChoosenEvent {
+                choosen_event: String::new(),
+                choice_box_name: cbs.choice_box_name.clone(),
+                index: cbs.target_list.len(),
+            };
+            commands.queue(move |w: &mut World| {
+                w.send_event(BdsEvent {
+                    value: Box::new(none),
+                    dialog_box: Some(cb_entity),
+                    source: BdsSource::default(),
+                });
+            });
+        }
+        commands.entity(countdown.bar).despawn();
+        commands.entity(cb_entity).remove::<ChoiceCountdown>();
+    }
+}
+
 fn get_rect(tf: &GlobalTransform, sp: &Sprite) -> Rect {
     let base_size = sp.custom_size.unwrap_or_default();
     let bottom_left = Vec2::new(tf.translation().x, tf.translation().y - base_size.y);
@@ -233,29 +564,65 @@ fn get_rect(tf: &GlobalTransform, sp: &Sprite) -> Rect {
 
 pub(in crate::writing) fn close_choice_phase(
     mut commands: Commands,
-    cbs_query: Query<&ChoiceBoxState>,
+    cbs_query: Query<(Entity, &ChoiceBoxState)>,
+    countdown_query: Query<&ChoiceCountdown>,
     mut db_query: Query<(Entity, &DialogBox, &mut DialogBoxPhase)>,
     mut events: EventReader<BdsEvent>,
+    mut lifecycle_event: EventWriter<DialogLifecycle>,
     app_type_registry: Res<AppTypeRegistry>,
 ) {
     for event_wrapper in events.read() {
         if let Some(ChoosenEvent {
             choosen_event: ce,
             choice_box_name: cb_name,
+            index,
         }) = event_wrapper.get::<ChoosenEvent>()
         {
+            let cbs_opt = cbs_query
+                .iter()
+                .find(|x| event_wrapper.is_for(x.0, x.1.choice_box_name == cb_name));
+            let main_box = cbs_opt.map(|x| x.1.main_box);
+            let main_source = BdsSource {
+                dialog_box_name: cbs_opt
+                    .map(|x| x.1.main_writing_name.clone())
+                    .unwrap_or_default(),
+                ..default()
+            };
             if let Ok(next) = read_ron(&app_type_registry, ce) {
-                commands.queue(|w: &mut World| {
-                    w.send_event(BdsEvent { value: next });
+                commands.queue(move |w: &mut World| {
+                    w.send_event(BdsEvent {
+                        value: next,
+                        dialog_box: main_box,
+                        source: main_source,
+                    });
                 });
             }
-            if let Some(cbs) = cbs_query.iter().find(|x| x.choice_box_name == cb_name) {
+            if let Some((cb_entity, cbs)) = cbs_opt {
+                if let Ok(countdown) = countdown_query.get(cb_entity) {
+                    commands.entity(countdown.bar).despawn();
+                    commands.entity(cb_entity).remove::<ChoiceCountdown>();
+                }
                 for (db_entity, db, mut dbp) in &mut db_query {
-                    if db.name == cbs.main_writing_name {
+                    if db_entity == cbs.main_box && db.name == cbs.main_writing_name {
+                        if let Some((script_index, label)) = cbs.made_choice(index) {
+                            lifecycle_event.write(DialogLifecycle {
+                                dialog_box: db_entity,
+                                writing_name: db.name.clone(),
+                                stage: LifecycleStage::ChoiceMade {
+                                    index: script_index,
+                                    label: label.to_string(),
+                                },
+                            });
+                        }
                         let close = BdsEvent {
                             value: Box::new(SinkDownWindow {
                                 sink_type: cbs.sinkdown,
                             }),
+                            dialog_box: Some(cb_entity),
+                            source: BdsSource {
+                                dialog_box_name: cbs.choice_box_name.clone(),
+                                ..default()
+                            },
                         };
                         commands.queue(|w: &mut World| {
                             w.send_event(close);
@@ -274,7 +641,8 @@ pub(in crate::writing) fn close_choice_phase(
 pub(in crate::writing) fn reinstatement_external_entities(
     mut commands: Commands,
     cbs_query: Query<(Entity, &ChoiceBoxState), With<Choosable>>,
-    cb_query: Query<(Entity, &ChoiceButton)>,
+    cb_query: Query<(Entity, &ChoiceButton), Without<ExtraChoiceButton>>,
+    extra_query: Query<(Entity, &ChildOf), With<ExtraChoiceButton>>,
     ta_query: Query<&TextArea>,
     children_query: Query<&Children>,
     mut sp_query: Query<&mut Sprite>,
@@ -294,16 +662,213 @@ pub(in crate::writing) fn reinstatement_external_entities(
                 }
                 let cb_entities = cb_query
                     .iter()
-                    .filter(|x| x.1.target_box_name == cbs.choice_box_name);
+                    .filter(|x| x.1.is_for(state_entity, &cbs.choice_box_name));
                 for (entity, _) in cb_entities {
                     if let Ok(mut tf) = tf_query.get_mut(entity) {
                         tf.translation.x -= x_dir * x_expand / 2.0;
                         tf.translation.y -= y_dir * y_expand / 2.0;
                     }
                 }
+                for (extra_entity, _) in extra_query.iter().filter(|x| x.1.parent() == state_entity)
+                {
+                    commands.entity(extra_entity).despawn();
+                }
                 commands.entity(state_entity).remove::<ChoiceBoxState>();
                 commands.entity(state_entity).remove::<Choosable>();
             }
         }
     }
 }
+
+#[cfg(test)]
+mod choice_tests {
+    use super::*;
+
+    fn area(name: &str, y: f32) -> TextAreaConfig {
+        TextAreaConfig {
+            area_name: name.to_string(),
+            area_origin: Vec2::new(0.0, y),
+            area_size: Vec2::new(200.0, 40.0),
+            ..default()
+        }
+    }
+
+    fn config(count: usize, overflow: ChoiceOverflow) -> ChoiceBoxConfig {
+        ChoiceBoxConfig {
+            button_text_areas: (0..count)
+                .map(|i| area(&format!("Button Area {:02}", i + 1), -100.0 * i as f32))
+                .collect(),
+            overflow,
+            ..default()
+        }
+    }
+
+    fn choices(count: usize) -> (Vec<(String, String)>, Vec<ChoiceSlot>) {
+        (0..count)
+            .map(|i| {
+                let slot = ChoiceSlot {
+                    enabled: i % 2 == 0,
+                    script_index: Some(i),
+                };
+                ((format!("c{i}"), format!("e{i}")), slot)
+            })
+            .unzip()
+    }
+
+    fn enabled(slots: &[ChoiceSlot]) -> Vec<bool> {
+        slots.iter().map(|s| s.enabled).collect()
+    }
+
+    fn labels(list: &[(String, String)]) -> Vec<&str> {
+        list.iter().map(|c| c.0.as_str()).collect()
+    }
+
+    #[test]
+    fn test_fit_without_overflow() {
+        let cbc = config(3, ChoiceOverflow::default());
+        let (tl, slots, areas) = fit_choices_to_areas(&cbc, choices(2), 0, "next");
+        assert_eq!(labels(&tl), vec!["c0", "c1"]);
+        assert_eq!(enabled(&slots), vec![true, false]);
+        assert_eq!(areas.len(), 2);
+        let (tl, _, areas) = fit_choices_to_areas(&config(0, default()), choices(2), 0, "next");
+        assert!(tl.is_empty() && areas.is_empty());
+    }
+
+    #[test]
+    fn test_fit_paging() {
+        let cbc = config(3, ChoiceOverflow::default());
+        let (tl, slots, areas) = fit_choices_to_areas(&cbc, choices(5), 0, "next");
+        assert_eq!(labels(&tl), vec!["c0", "c1", "Next"]);
+        assert_eq!(tl[2].1, "next");
+        assert_eq!(slots[2].script_index, None);
+        assert_eq!(enabled(&slots), vec![true, false, true]);
+        assert_eq!(areas.len(), 3);
+        let (tl, slots, _) = fit_choices_to_areas(&cbc, choices(5), 2, "next");
+        assert_eq!(labels(&tl), vec!["c4", "Next"]);
+        assert_eq!(slots[0].script_index, Some(4));
+        // 最後の頁の次は最初の頁に戻ります。
+        let (tl, _, _) = fit_choices_to_areas(&cbc, choices(5), 3, "next");
+        assert_eq!(labels(&tl), vec!["c0", "c1", "Next"]);
+    }
+
+    #[test]
+    fn test_fit_paging_with_one_area_extends() {
+        let cbc = config(1, ChoiceOverflow::default());
+        let (tl, slots, areas) = fit_choices_to_areas(&cbc, choices(3), 0, "next");
+        assert_eq!(labels(&tl), vec!["c0", "c1", "c2"]);
+        assert_eq!(enabled(&slots), vec![true, false, true]);
+        let origins = areas.iter().map(|a| a.area_origin.y).collect::<Vec<_>>();
+        assert_eq!(origins, vec![0.0, -40.0, -80.0]);
+    }
+
+    #[test]
+    fn test_fit_extend() {
+        let step = Vec2::new(0.0, -100.0);
+        let cbc = config(2, ChoiceOverflow::Extend { step });
+        let (tl, _, areas) = fit_choices_to_areas(&cbc, choices(4), 0, "next");
+        assert_eq!(labels(&tl), vec!["c0", "c1", "c2", "c3"]);
+        let names = areas
+            .iter()
+            .map(|a| a.area_name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec![
+                "Button Area 01",
+                "Button Area 02",
+                "Button Area 03",
+                "Button Area 04"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_filter_choices_keeps_script_index() {
+        let mut variables = ScriptVariables::default();
+        variables.set("gold", 3);
+        let conditions = [
+            ChoiceCondition {
+                show_if: "has_key".to_string(),
+                ..default()
+            },
+            ChoiceCondition {
+                enable_if: "gold>=10".to_string(),
+                ..default()
+            },
+        ];
+        let (tl, slots) = filter_choices(choices(3).0, &conditions, &variables);
+        assert_eq!(labels(&tl), vec!["c1", "c2"]);
+        let expected = [
+            ChoiceSlot {
+                enabled: false,
+                script_index: Some(1),
+            },
+            ChoiceSlot {
+                enabled: true,
+                script_index: Some(2),
+            },
+        ];
+        assert_eq!(slots, expected);
+    }
+
+    fn state(
+        cbc: &ChoiceBoxConfig,
+        fitted: (Vec<(String, String)>, Vec<ChoiceSlot>),
+    ) -> ChoiceBoxState {
+        ChoiceBoxState {
+            main_box: Entity::PLACEHOLDER,
+            main_writing_name: "Main Box".to_string(),
+            text_area_names: vec![],
+            choice_box_name: cbc.choice_box_name.clone(),
+            target_list: fitted.0,
+            slots: fitted.1,
+            select_vector: cbc.select_vector,
+            sinkdown: cbc.sinkdown,
+            background_scaling_per_button: cbc.background_scaling_per_button,
+            background_scaling_anchor: cbc.background_scaling_anchor,
+        }
+    }
+
+    #[test]
+    fn test_made_choice_is_script_index() {
+        let cbc = config(3, ChoiceOverflow::default());
+        let hidden = [ChoiceCondition {
+            show_if: "has_key".to_string(),
+            ..default()
+        }];
+        let filtered = filter_choices(choices(5).0, &hidden, &ScriptVariables::default());
+        let (tl, slots, _) = fit_choices_to_areas(&cbc, filtered.clone(), 0, "next");
+        let first = state(&cbc, (tl, slots));
+        assert_eq!(first.made_choice(0), Some((1, "c1")));
+        assert_eq!(first.made_choice(1), Some((2, "c2")));
+        // 頁送りと範囲外は選ばれたことになりません。
+        assert_eq!(first.made_choice(2), None);
+        assert_eq!(first.made_choice(3), None);
+        let (tl, slots, _) = fit_choices_to_areas(&cbc, filtered, 1, "next");
+        let second = state(&cbc, (tl, slots));
+        assert_eq!(second.made_choice(1), Some((4, "c4")));
+    }
+
+    #[test]
+    fn test_extended_area() {
+        let base = [area("A 1", 0.0), area("A 2", -50.0)];
+        let step = Vec2::new(10.0, -50.0);
+        assert_eq!(
+            extended_area(&base, 1, step).area_origin,
+            Vec2::new(0.0, -50.0)
+        );
+        let third = extended_area(&base, 3, step);
+        assert_eq!(third.area_name, "A 4");
+        assert_eq!(third.area_origin, Vec2::new(20.0, -150.0));
+        assert_eq!(third.area_size, base[1].area_size);
+    }
+
+    #[test]
+    fn test_numbered_name() {
+        assert_eq!(numbered_name("Button Area 02", 1), "Button Area 03");
+        assert_eq!(numbered_name("Button Area 09", 3), "Button Area 12");
+        assert_eq!(numbered_name("Area 99", 1), "Area 100");
+        assert_eq!(numbered_name("Area7", 2), "Area9");
+        assert_eq!(numbered_name("Button", 2), "Button 2");
+    }
+}
@@ -0,0 +1,86 @@
+use std::collections::HashMap;
+
+use super::sinkdown::GoSinking;
+use super::*;
+use crate::writing::events::*;
+use crate::writing::text_controller::feed_animation::FeedWaitingEvent;
+
+// DialogBoxPhaseの変化を前回の値と比べてDialogLifecycleにします。
+// 選択肢の開閉はchoice.rsで直接発行しています。
+#[allow(clippy::type_complexity)]
+pub(in crate::writing) fn send_lifecycle_events(
+    db_query: Query<(Entity, &DialogBox, &DialogBoxPhase), Changed<DialogBoxPhase>>,
+    name_query: Query<&DialogBox>,
+    mut feed_events: EventReader<FeedWaitingEvent>,
+    mut sink_events: EventReader<GoSinking>,
+    mut closed_events: EventReader<FinisClosingBox>,
+    mut lifecycle_event: EventWriter<DialogLifecycle>,
+    mut last_phases: Local<HashMap<Entity, DialogBoxPhase>>,
+) {
+    let mut send = |dialog_box: Entity, writing_name: &str, stage: LifecycleStage| {
+        lifecycle_event.write(DialogLifecycle {
+            dialog_box,
+            writing_name: writing_name.to_string(),
+            stage,
+        });
+    };
+    for (db_entity, db, phase) in &db_query {
+        let last = last_phases.insert(db_entity, *phase);
+        if last == Some(*phase) {
+            continue;
+        }
+        if last.is_none() {
+            send(db_entity, &db.name, LifecycleStage::Opened);
+        }
+        match phase {
+            DialogBoxPhase::Typing => {
+                if last == Some(DialogBoxPhase::PoppingUp) {
+                    send(db_entity, &db.name, LifecycleStage::PoppedUp);
+                }
+                send(db_entity, &db.name, LifecycleStage::StartedTyping);
+            }
+            DialogBoxPhase::WaitingAction => {
+                send(db_entity, &db.name, LifecycleStage::WaitingForInput);
+            }
+            DialogBoxPhase::Feeding => {
+                send(db_entity, &db.name, LifecycleStage::Feeding);
+            }
+            DialogBoxPhase::SinkingDown => {
+                send(db_entity, &db.name, LifecycleStage::Closing);
+            }
+            _ => (),
+        }
+    }
+    for event in feed_events.read() {
+        if let Ok(db) = name_query.get(event.target_box) {
+            send(event.target_box, &db.name, LifecycleStage::PageFinished);
+        }
+    }
+    // SinkDownType::Fixはその場で閉じ終わります。
+    for event in sink_events.read() {
+        if event.sink_type != SinkDownType::Fix {
+            continue;
+        }
+        let target = match event.target {
+            Some(entity) => Some(entity),
+            None => last_phases.keys().copied().find(|e| {
+                name_query
+                    .get(*e)
+                    .is_ok_and(|x| x.name == event.writing_name)
+            }),
+        };
+        if let Some(entity) = target {
+            send(entity, &event.writing_name, LifecycleStage::Closing);
+            send(entity, &event.writing_name, LifecycleStage::Closed);
+            last_phases.remove(&entity);
+        }
+    }
+    for event in closed_events.read() {
+        send(
+            event.dialog_box,
+            &event.writing_name,
+            LifecycleStage::Closed,
+        );
+        last_phases.remove(&event.dialog_box);
+    }
+}
@@ -1,23 +1,27 @@
 use bevy::{prelude::*, sprite::Anchor};
 
 pub mod choice;
+pub mod lifecycle;
 pub mod popup;
 pub mod sinkdown;
 pub mod waiting;
 
 use super::setup::SetupConfig;
+use super::text_controller::TypingCursor;
 use crate::read_script::*;
 use crate::writing::settings::configs::*;
 use crate::writing::settings::params::*;
 use crate::writing::OpenDialog;
 
+/// A dialog box. Spawned by [`OpenDialog`].
 #[derive(Component)]
-pub(crate) struct DialogBox {
+pub struct DialogBox {
     pub name: String,
 }
 
+/// A text area in a [`DialogBox`].
 #[derive(Component)]
-pub(crate) struct TextArea {
+pub struct TextArea {
     pub name: String,
 }
 
@@ -41,6 +45,8 @@ struct DialogBoxBundle {
     waitting: WaitBrakerStyle,
     script: LoadedScript,
     popup_type: PopupType,
+    cursor: TypingCursor,
+    missing_section: MissingSectionPolicy,
 }
 
 #[derive(Bundle)]
@@ -48,10 +54,12 @@ struct TextAreaBundle {
     text_area: TextArea,
     feeding: FeedingStyle,
     config: TypeTextConfig,
+    fitting: AreaFitting,
 }
 
+/// What a [`DialogBox`] is doing now.
 #[derive(Component, Debug, Clone, Copy, PartialEq)]
-pub(crate) enum DialogBoxPhase {
+pub enum DialogBoxPhase {
     Preparing,
     PoppingUp,
     WaitToType,
@@ -1,5 +1,5 @@
 use super::*;
-use crate::read_script::split_path_and_section;
+use crate::read_script::{load_script, split_path_and_section};
 use bevy::render::view::{RenderLayers, Visibility::*};
 
 pub(in crate::writing) fn open_window(
@@ -20,20 +20,25 @@ pub(in crate::writing) fn open_window(
         let loaded_script = if window_config.raw_orders.is_some() {
             LoadedScript {
                 bds_handle_opt: None,
-                bdt_handle_list: Vec::new(),
+                templates: Vec::new(),
                 target_section: script_section,
                 order_list: window_config.raw_orders.clone(),
+                section_len: window_config.raw_orders.as_ref().map_or(0, Vec::len),
+                call_stack: Vec::new(),
             }
         } else {
+            let templates = window_config
+                .template_path
+                .iter()
+                .map(|path| asset_server.load(path.clone()))
+                .collect::<Vec<_>>();
             LoadedScript {
-                bds_handle_opt: Some(asset_server.load(script_path)),
-                bdt_handle_list: window_config
-                    .template_path
-                    .iter()
-                    .map(|x| asset_server.load(x.clone()))
-                    .collect(),
+                bds_handle_opt: Some(load_script(&asset_server, script_path, &templates)),
+                templates,
                 target_section: script_section,
                 order_list: None,
+                section_len: 0,
+                call_stack: Vec::new(),
             }
         };
         let mwb = DialogBoxBundle {
@@ -44,16 +49,23 @@ pub(in crate::writing) fn open_window(
             waitting: window_config.wait_breaker.clone(),
             script: loaded_script,
             popup_type: window_config.popup,
+            cursor: TypingCursor::default(),
+            missing_section: window_config.missing_section.clone(),
         };
         let mw_spirte = (
             Sprite::default(),
             Transform::from_translation(window_config.position.extend(0.0)),
         );
-        let bg_opt = bg_query
-            .iter()
-            .find(|x| x.1.writing_name == window_config.writing_name);
+        let bg_opt = match window_config.target {
+            Some(entity) => Some(entity),
+            None => bg_query
+                .iter()
+                .find(|x| x.1.writing_name == window_config.writing_name)
+                .map(|x| x.0),
+        };
         let mw = match bg_opt {
-            Some((entity, _)) => entity,
+            Some(entity) if bg_query.contains(entity) => entity,
+            Some(entity) => commands.entity(entity).insert((mw_spirte, Instant)).id(),
             None => commands.spawn((mw_spirte, Instant)).id(),
         };
         if let Ok(mut tf) = tf_query.get_mut(mw) {
@@ -72,11 +84,8 @@ pub(in crate::writing) fn open_window(
                     name: t_cfg.area_name.clone(),
                 },
                 feeding: t_cfg.feeding,
-                config: initialize_text_config(
-                    asset_server.clone(),
-                    t_cfg,
-                    Res::clone(&setup_config),
-                ),
+                config: initialize_text_config(&asset_server, t_cfg, setup_config.render_layer),
+                fitting: t_cfg.fitting,
             };
             let ta_sprite = (
                 Sprite {
@@ -105,10 +114,10 @@ pub(in crate::writing) fn open_window(
     }
 }
 
-fn initialize_text_config(
-    asset_server: AssetServer,
+pub(in crate::writing) fn initialize_text_config(
+    asset_server: &AssetServer,
     t_cfg: &TextAreaConfig,
-    setup_config: Res<SetupConfig>,
+    render_layer: u8,
 ) -> TypeTextConfig {
     let text_font_vec = t_cfg
         .text_config
@@ -120,19 +129,30 @@ fn initialize_text_config(
             ..default()
         })
         .collect::<Vec<_>>();
+    let font_scripts = t_cfg
+        .text_config
+        .font_settings
+        .iter()
+        .map(|f| f.scripts.clone())
+        .collect::<Vec<_>>();
     TypeTextConfig {
         text_fonts: text_font_vec,
+        font_scripts,
+        keep_punctuation_in_run: t_cfg.text_config.keep_punctuation_in_run,
         kerning_by_regulars: t_cfg.text_config.kerning_by_regulars.clone(),
         size_by_regulars: t_cfg.text_config.size_by_regulars.clone(),
         text_color: t_cfg.text_config.font_color,
         writing: t_cfg.writing,
         base_size: t_cfg.text_config.text_base_size,
         typing_timing: t_cfg.typing_timing,
-        layer: RenderLayers::layer(setup_config.render_layer.into()),
+        layer: RenderLayers::layer(render_layer.into()),
         horizon_alignment: t_cfg.horizon_alignment,
         vertical_alignment: t_cfg.vertical_alignment,
         monospace: t_cfg.monospace,
         pos_z: t_cfg.text_pos_z,
+        rendering: t_cfg.rendering,
+        area_origin: t_cfg.area_origin,
+        area_size: t_cfg.area_size,
     }
 }
 
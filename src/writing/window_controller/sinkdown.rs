@@ -1,3 +1,4 @@
+use super::choice::ChoiceBoxState;
 use super::*;
 use crate::writing::*;
 
@@ -19,21 +20,33 @@ pub(in crate::writing) struct WaitSinkingTrigger {
 pub(in crate::writing) struct GoSinking {
     pub writing_name: String,
     pub sink_type: SinkDownType,
+    #[reflect(ignore)]
+    pub target: Option<Entity>,
 }
 
 #[allow(clippy::type_complexity)]
 pub(in crate::writing) fn setup_window_sink(
     mut commands: Commands,
     text_query: Query<(Entity, &TypingTimer), (With<Current>, With<MessageTextChar>)>,
+    batched_query: Query<(Entity, &BatchedLine)>,
     text_box_query: Query<(Entity, &TextArea, &GlobalTransform, &Sprite), With<Current>>,
-    mut db_query: Query<(Entity, &DialogBox, &mut DialogBoxPhase, &WaitBrakerStyle), With<Current>>,
+    mut db_query: Query<(
+        Entity,
+        &DialogBox,
+        &mut DialogBoxPhase,
+        &WaitBrakerStyle,
+        Has<Current>,
+    )>,
     parents: Query<&ChildOf>,
     mut events: EventReader<BdsEvent>,
     type_registry: Res<AppTypeRegistry>,
 ) {
     for event_wrapper in events.read() {
         if let Some(SinkDownWindow { sink_type: sdt }) = event_wrapper.get::<SinkDownWindow>() {
-            for (mw_entity, db, mut ws, wbs) in &mut db_query {
+            for (mw_entity, db, mut ws, wbs, is_current) in &mut db_query {
+                if !event_wrapper.is_for(mw_entity, is_current) {
+                    continue;
+                }
                 match wbs {
                     WaitBrakerStyle::Auto { wait_sec: base_sec } => {
                         let count: f32 = text_query
@@ -43,9 +56,19 @@ pub(in crate::writing) fn setup_window_sink(
                             })
                             .map(|(_, tt)| tt.timer.remaining_secs())
                             .sum();
+                        let batched_sec = batched_query
+                            .iter()
+                            .filter(|(l_entity, _)| {
+                                parents.iter_ancestors(*l_entity).any(|x| x == mw_entity)
+                            })
+                            .map(|(_, line)| line.remaining_secs())
+                            .fold(0.0, f32::max);
                         commands.entity(mw_entity).insert(WaitSinkingTrigger {
                             sink_type: sdt,
-                            timer: Timer::from_seconds(base_sec + count, TimerMode::Once),
+                            timer: Timer::from_seconds(
+                                base_sec + count + batched_sec,
+                                TimerMode::Once,
+                            ),
                         });
                     }
                     WaitBrakerStyle::Input { .. } => {
@@ -59,6 +82,7 @@ pub(in crate::writing) fn setup_window_sink(
                                 GoSinking {
                                     writing_name: db.name.clone(),
                                     sink_type: sdt,
+                                    ..default()
                                 },
                             );
                             let mf_config = MakeWigConfig {
@@ -84,8 +108,11 @@ pub(in crate::writing) fn trigger_window_sink_by_event(
     mut gs_writer: EventWriter<GoSinking>,
 ) {
     for event_wrapper in bds_reader.read() {
-        if let Some(gs @ GoSinking { .. }) = event_wrapper.get::<GoSinking>() {
-            gs_writer.write(gs);
+        if let Some(gs) = event_wrapper.get::<GoSinking>() {
+            gs_writer.write(GoSinking {
+                target: event_wrapper.dialog_box,
+                ..gs
+            });
         }
     }
 }
@@ -101,6 +128,7 @@ pub(in crate::writing) fn trigger_window_sink_by_time(
             events.write(GoSinking {
                 writing_name: db.name.clone(),
                 sink_type: wst.sink_type,
+                target: Some(entity),
             });
             commands.entity(entity).remove::<WaitSinkingTrigger>();
         }
@@ -115,10 +143,11 @@ pub(in crate::writing) fn start_window_sink(
     for GoSinking {
         writing_name: db_name,
         sink_type: st,
+        target,
     } in &mut events.read()
     {
         for (entity, db, mut ws) in &mut db_query {
-            if db.name == *db_name {
+            if target.map_or(db.name == *db_name, |e| e == entity) {
                 match st {
                     SinkDownType::Scale { sec: s } => {
                         commands.entity(entity).insert(ScalingDown {
@@ -194,31 +223,43 @@ pub(in crate::writing) fn despawn_writing(
         }
         event.write(FinisClosingBox {
             writing_name: db.name.clone(),
+            dialog_box: db_entity,
         });
     }
 }
 
 #[allow(clippy::type_complexity)]
+// 選択肢を開いた元のDialogBoxは、その選択肢が閉じ終わるまでPendingのまま待ちます。
 pub(in crate::writing) fn remove_pending(
     mut commands: Commands,
     mut pending_query: Query<(Entity, &mut DialogBoxPhase, &Pending), With<DialogBox>>,
-    current_db_query: Query<&Current, (With<DialogBox>, Without<Pending>)>,
+    choice_query: Query<(&ChoiceBoxState, &DialogBoxPhase), Without<Pending>>,
+    current_db_query: Query<(), (With<DialogBox>, With<Current>)>,
     children_query: Query<&Children>,
 ) {
-    if current_db_query.iter().next().is_none() {
-        if let Ok((db_entity, mut dbp, pd)) = pending_query.single_mut() {
-            if pd.name == "Waiting Sink" {
-                commands.entity(db_entity).remove::<Pending>();
-                commands.entity(db_entity).insert(Current);
-                if let Ok(children) = children_query.get(db_entity) {
-                    for childe in children {
-                        commands.entity(*childe).remove::<Pending>();
-                    }
-                }
-                if *dbp == DialogBoxPhase::WaitToType {
-                    *dbp = DialogBoxPhase::Typing;
-                }
+    let mut has_current = !current_db_query.is_empty();
+    for (db_entity, mut dbp, pd) in &mut pending_query {
+        if pd.name != "Waiting Sink" {
+            continue;
+        }
+        let is_choosing = choice_query
+            .iter()
+            .any(|(cbs, phase)| cbs.main_box == db_entity && *phase != DialogBoxPhase::Fixed);
+        if is_choosing {
+            continue;
+        }
+        commands.entity(db_entity).remove::<Pending>();
+        if !has_current {
+            commands.entity(db_entity).insert(Current);
+            has_current = true;
+        }
+        if let Ok(children) = children_query.get(db_entity) {
+            for childe in children {
+                commands.entity(*childe).remove::<Pending>();
             }
         }
+        if *dbp == DialogBoxPhase::WaitToType {
+            *dbp = DialogBoxPhase::Typing;
+        }
     }
 }
@@ -30,13 +30,16 @@ pub(crate) struct MakeWigConfig<'a, S: AsRef<str>> {
 #[allow(clippy::type_complexity)]
 pub(in crate::writing) fn simple_wait(
     mut commands: Commands,
-    mut dialog_query: Query<
-        (Entity, &mut DialogBoxPhase, &DialogBox, &WaitBrakerStyle),
-        With<Current>,
-    >,
+    mut dialog_query: Query<(
+        Entity,
+        &mut DialogBoxPhase,
+        &DialogBox,
+        &WaitBrakerStyle,
+        Has<Current>,
+    )>,
     w_icon_query: Query<(Entity, &WaitingIcon)>,
     text_area_query: Query<(Entity, &TextArea, &GlobalTransform, &Sprite, &ChildOf), With<Current>>,
-    selected_query: Query<Entity, With<Selected>>,
+    selected_query: Query<(Entity, &ChildOf), With<Selected>>,
     last_data: CurrentQuery,
     mut bds_reader: EventReader<BdsEvent>,
     type_registry: Res<AppTypeRegistry>,
@@ -45,38 +48,42 @@ pub(in crate::writing) fn simple_wait(
         if event_wrapper.get::<SimpleWait>() != Some(SimpleWait) {
             continue;
         }
-        for (mw_entity, mut ws, DialogBox { name: db_name }, wbs) in &mut dialog_query {
+        for (mw_entity, mut ws, DialogBox { name: db_name }, wbs, is_current) in &mut dialog_query {
+            if !event_wrapper.is_for(mw_entity, is_current) {
+                continue;
+            }
             for (ta_entity, ta, tb_tf, tb_sp, parent) in &text_area_query {
-                if parent.parent() == mw_entity {
-                    let ron = write_ron(
-                        &type_registry,
-                        BreakWait {
-                            writing_name: db_name.clone(),
-                            text_area_name: ta.name.clone(),
-                        },
-                    )
-                    .unwrap_or_default();
-                    let mf_config = MakeWigConfig {
-                        dialog_box_name: db_name,
-                        text_area_name: &ta.name,
-                        waiter_name: &"".to_string(),
-                        ron: &ron,
-                        type_registry: &type_registry,
-                    };
-                    let wig = if let WaitBrakerStyle::Input {
-                        is_all_range_area: true,
-                        ..
-                    } = wbs
-                    {
-                        make_wig_for_skip_all_range(mf_config)
-                    } else {
-                        make_wig_for_skip(mf_config, tb_tf, tb_sp)
-                    };
-                    commands.entity(ta_entity).insert(wig);
+                if parent.parent() != mw_entity {
+                    continue;
                 }
+                let ron = write_ron(
+                    &type_registry,
+                    BreakWait {
+                        writing_name: db_name.clone(),
+                        text_area_name: ta.name.clone(),
+                    },
+                )
+                .unwrap_or_default();
+                let mf_config = MakeWigConfig {
+                    dialog_box_name: db_name,
+                    text_area_name: &ta.name,
+                    waiter_name: &"".to_string(),
+                    ron: &ron,
+                    type_registry: &type_registry,
+                };
+                let wig = if let WaitBrakerStyle::Input {
+                    is_all_range_area: true,
+                    ..
+                } = wbs
+                {
+                    make_wig_for_skip_all_range(mf_config)
+                } else {
+                    make_wig_for_skip(mf_config, tb_tf, tb_sp)
+                };
+                commands.entity(ta_entity).insert(wig);
                 let (_, last_char) = initialize_typing_data(&last_data, ta_entity);
                 let ic_opt = w_icon_query.iter().find(|x| {
-                    x.1.target_box_name == *db_name
+                    x.1.is_for(mw_entity, db_name)
                         && x.1.wait_for.contains(&WaitTarget::SimpleWaiting)
                 });
                 if let Some((ic_entity, _)) = ic_opt {
@@ -87,8 +94,10 @@ pub(in crate::writing) fn simple_wait(
                     commands.entity(ic_entity).insert(tt);
                     commands.entity(ic_entity).insert(ChildOf(ta_entity));
                 }
-                for s_entity in &selected_query {
-                    commands.entity(s_entity).remove::<Selected>();
+                for (s_entity, s_parent) in &selected_query {
+                    if s_parent.parent() == mw_entity {
+                        commands.entity(s_entity).remove::<Selected>();
+                    }
                 }
                 commands.entity(ta_entity).insert(Selected);
             }
@@ -99,8 +108,8 @@ pub(in crate::writing) fn simple_wait(
 
 pub(in crate::writing) fn restart_typing(
     mut commands: Commands,
-    mut writing_query: Query<(&DialogBox, &mut DialogBoxPhase)>,
-    text_area_query: Query<&TextArea>,
+    mut writing_query: Query<(Entity, &DialogBox, &mut DialogBoxPhase)>,
+    text_area_query: Query<(&TextArea, &ChildOf)>,
     mut icon_query: Query<(Entity, &mut Visibility, &mut WaitingIcon)>,
     mut bds_reader: EventReader<BdsEvent>,
 ) {
@@ -110,16 +119,17 @@ pub(in crate::writing) fn restart_typing(
             text_area_name: target_ta_name,
         }) = event_wrapper.get::<BreakWait>()
         {
-            for (DialogBox { name: db_name }, mut phase) in &mut writing_query {
-                for TextArea { name: ta_name } in &text_area_query {
-                    if *db_name == target_db_name
+            for (db_entity, DialogBox { name: db_name }, mut phase) in &mut writing_query {
+                for (TextArea { name: ta_name }, ta_parent) in &text_area_query {
+                    if event_wrapper.is_for(db_entity, *db_name == target_db_name)
+                        && ta_parent.parent() == db_entity
                         && *ta_name == target_ta_name
                         && DialogBoxPhase::WaitingAction == *phase
                     {
                         *phase = DialogBoxPhase::Typing;
                         let ic_opt = icon_query
                             .iter_mut()
-                            .find(|x| x.2.target_box_name == *db_name);
+                            .find(|x| x.2.is_for(db_entity, db_name));
                         if let Some((ic_entity, mut ic_vis, _)) = ic_opt {
                             commands.entity(ic_entity).remove::<TypingStyle>();
                             commands.entity(ic_entity).remove::<TypingTimer>();
@@ -135,13 +145,12 @@ pub(in crate::writing) fn restart_typing(
 pub(in crate::writing) fn waiting_icon_setting(
     mut commands: Commands,
     w_icon_query: Query<(Entity, &WaitingIcon), Without<WritingStyle>>,
-    wbs_query: Query<(&RenderLayers, &WaitBrakerStyle, &DialogBox)>,
+    wbs_query: Query<(Entity, &RenderLayers, &WaitBrakerStyle, &DialogBox)>,
 ) {
-    for (layer, wbs, DialogBox { name: db_name }) in &wbs_query {
+    for (db_entity, layer, wbs, DialogBox { name: db_name }) in &wbs_query {
         if let WaitBrakerStyle::Input { .. } = wbs {
-            if let Some((ic_entity, _)) = w_icon_query
-                .iter()
-                .find(|x| x.1.target_box_name == *db_name)
+            if let Some((ic_entity, _)) =
+                w_icon_query.iter().find(|x| x.1.is_for(db_entity, db_name))
             {
                 commands.entity(ic_entity).insert((
                     WritingStyle::Put,
@@ -178,7 +187,7 @@ pub(in crate::writing) fn settle_wating_icon(
         {
             if *ws == DialogBoxPhase::WaitingAction {
                 for (ic_entity, mut ic_tf, wi) in &mut float_icon_query {
-                    if wi.target_box_name == *db_name {
+                    if wi.is_for(mw_entity, db_name) {
                         if let Some((tb_entity, _, config)) = text_box_query
                             .iter()
                             .find(|(_, p, _)| p.parent() == mw_entity)
@@ -194,7 +203,7 @@ pub(in crate::writing) fn settle_wating_icon(
                 }
             } else {
                 for (ic_entity, wi) in &settle_icon_query {
-                    if wi.target_box_name == *db_name {
+                    if wi.is_for(mw_entity, db_name) {
                         commands.entity(ic_entity).remove::<Settled>();
                     }
                 }
@@ -223,16 +232,13 @@ pub(in crate::writing) fn skip_typing_or_next(
         With<MessageTextChar>,
     >,
     mut typing_texts_query: Query<(Entity, &mut TypingStyle, &ChildOf), With<MessageTextChar>>,
-    writing_query: Query<(&DialogBox, &DialogBoxPhase, &WaitBrakerStyle)>,
-    text_area_query: Query<(Entity, &TextArea, &GlobalTransform, &Sprite)>,
+    writing_query: Query<(Entity, &DialogBox, &DialogBoxPhase, &WaitBrakerStyle)>,
+    text_area_query: Query<(Entity, &TextArea, &GlobalTransform, &Sprite, &ChildOf)>,
     line_query: Query<(Entity, &ChildOf), With<MessageTextLine>>,
+    mut batched_query: Query<(&mut BatchedLine, &ChildOf)>,
     mut icon_query: Query<
-        (Entity, &mut Visibility),
-        (
-            With<WaitingIcon>,
-            With<TypingTimer>,
-            Without<MessageTextChar>,
-        ),
+        (Entity, &mut Visibility, &WaitingIcon),
+        (With<TypingTimer>, Without<MessageTextChar>),
     >,
     mut bds_reader: EventReader<BdsEvent>,
     type_registry: Res<AppTypeRegistry>,
@@ -244,12 +250,18 @@ pub(in crate::writing) fn skip_typing_or_next(
             text_area_name: target_ta_name,
         }) = event_wrapper.get::<InputForSkipping>()
         {
-            let db_opt = writing_query.iter().find(|x| x.0.name == target_db_name);
-            let ta_opt = text_area_query.iter().find(|x| x.1.name == target_ta_name);
-            if let (Some((db, phase, wbs)), Some((ta_entity, ta, tb_tf, tb_sp))) = (db_opt, ta_opt)
-            {
+            let db_opt = writing_query
+                .iter()
+                .find(|x| event_wrapper.is_for(x.0, x.1.name == target_db_name));
+            let Some((db_entity, db, phase, wbs)) = db_opt else {
+                continue;
+            };
+            let ta_opt = text_area_query
+                .iter()
+                .find(|x| x.4.parent() == db_entity && x.1.name == target_ta_name);
+            if let Some((ta_entity, ta, tb_tf, tb_sp, _)) = ta_opt {
                 if *phase != DialogBoxPhase::WaitingAction {
-                    return;
+                    continue;
                 }
                 let mut typed_count = 0usize;
                 let mut text_count = 0usize;
@@ -266,15 +278,39 @@ pub(in crate::writing) fn skip_typing_or_next(
                         text_count += 1;
                     }
                 }
+                // まとめて描く行は一行を一文字分として数えます。
+                for (mut line, l_parent) in &mut batched_query {
+                    if l_parent.parent() == ta_entity {
+                        if line.is_revealed() {
+                            typed_count += 1;
+                        } else {
+                            line.reveal_all();
+                        }
+                        text_count += 1;
+                    }
+                }
                 if text_count <= typed_count {
-                    for (ic_entity, mut ic_vis) in &mut icon_query {
+                    for (ic_entity, mut ic_vis, wi) in &mut icon_query {
+                        if !wi.is_for(db_entity, &db.name) {
+                            continue;
+                        }
                         *ic_vis = Visibility::Hidden;
                         commands.entity(ic_entity).remove::<TypingTimer>();
                         commands.entity(ic_entity).remove::<TypingStyle>();
                     }
                     if let Ok(ref_value) = read_ron(&type_registry, ron.clone()) {
-                        commands.queue(|w: &mut World| {
-                            w.send_event(BdsEvent { value: ref_value });
+                        let source = BdsSource {
+                            dialog_box_name: db.name.clone(),
+                            text_area: Some(ta_entity),
+                            text_area_name: ta.name.clone(),
+                            location: None,
+                        };
+                        commands.queue(move |w: &mut World| {
+                            w.send_event(BdsEvent {
+                                value: ref_value,
+                                dialog_box: Some(db_entity),
+                                source,
+                            });
                         })
                     }
                 } else {
@@ -295,8 +331,10 @@ pub(in crate::writing) fn skip_typing_or_next(
                         make_wig_for_skip(mf_config, tb_tf, tb_sp)
                     };
                     commands.entity(ta_entity).insert(wig);
-                    for (_, mut ic_vis) in &mut icon_query {
-                        *ic_vis = Visibility::Inherited;
+                    for (_, mut ic_vis, wi) in &mut icon_query {
+                        if wi.is_for(db_entity, &db.name) {
+                            *ic_vis = Visibility::Inherited;
+                        }
                     }
                 }
                 for (text_entity, mut t_vis, mut tf, t_parent, mut tt) in &mut waiting_text_query {
@@ -315,8 +353,8 @@ pub(in crate::writing) fn skip_typing_or_next(
 
 pub(in crate::writing) fn skip_feeding(
     mut commands: Commands,
-    mut writing_query: Query<(&DialogBox, &mut DialogBoxPhase), With<Current>>,
-    text_area_query: Query<(Entity, &TextArea)>,
+    mut writing_query: Query<(Entity, &DialogBox, &mut DialogBoxPhase)>,
+    text_area_query: Query<(Entity, &TextArea, &ChildOf)>,
     line_query: Query<(Entity, &ChildOf), With<MessageTextLine>>,
     mut bds_reader: EventReader<BdsEvent>,
 ) {
@@ -329,9 +367,14 @@ pub(in crate::writing) fn skip_feeding(
         {
             let db_opt = writing_query
                 .iter_mut()
-                .find(|x| x.0.name == target_db_name);
-            let ta_opt = text_area_query.iter().find(|x| x.1.name == target_ta_name);
-            if let (Some((_, mut phase)), Some((ta_entity, _))) = (db_opt, ta_opt) {
+                .find(|x| event_wrapper.is_for(x.0, x.1.name == target_db_name));
+            let Some((db_entity, _, mut phase)) = db_opt else {
+                continue;
+            };
+            let ta_opt = text_area_query
+                .iter()
+                .find(|x| x.2.parent() == db_entity && x.1.name == target_ta_name);
+            if let Some((ta_entity, _, _)) = ta_opt {
                 if *phase == DialogBoxPhase::Feeding {
                     for (l_entity, l_parent) in &line_query {
                         if l_parent.parent() == ta_entity {
@@ -390,15 +433,13 @@ pub(in crate::writing) fn make_wig_for_skip_all_range<S: AsRef<str>>(
 
 pub(in crate::writing) fn hide_waiting_icon(
     mut icon_query: Query<(&WaitingIcon, &mut Visibility)>,
-    writing_query: Query<(&DialogBox, &DialogBoxPhase)>,
+    writing_query: Query<(Entity, &DialogBox, &DialogBoxPhase)>,
 ) {
-    if let Ok((icon, mut vis)) = icon_query.single_mut() {
-        let box_exists = writing_query
-            .iter()
-            .find(|x| x.0.name == icon.target_box_name);
-        if let Some((_, phase)) = box_exists {
+    for (icon, mut vis) in &mut icon_query {
+        let box_exists = writing_query.iter().find(|x| icon.is_for(x.0, &x.1.name));
+        if let Some((_, _, phase)) = box_exists {
             if *phase != DialogBoxPhase::SinkingDown {
-                return;
+                continue;
             }
         }
         *vis = Visibility::Hidden;